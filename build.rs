@@ -1,3 +1,4 @@
 fn main() {
+    #[cfg(feature = "gui")]
     slint_build::compile("ui/window.slint").expect("Slint build failed ");
 }
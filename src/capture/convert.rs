@@ -0,0 +1,205 @@
+use anyhow::Result;
+use rubato::{Resampler, SincFixedIn, SincInterpolationType, WindowFunction};
+
+use crate::downmix::{DownmixStrategy, Downmixer};
+
+pub const TARGET_SAMPLE_RATE: u32 = 16000;
+
+/// Mono frames handed to the resampler per call.
+const CHUNK_FRAMES: usize = 1024;
+
+/// Turns interleaved device audio into 16 kHz mono as it arrives.
+///
+/// Input may be split anywhere, even mid-frame. Output is produced one
+/// resampler chunk at a time; `flush` emits the tail once capture has stopped.
+pub struct StreamConverter {
+    downmixer: Downmixer,
+    ratio: f64,
+    resampler: Option<SincFixedIn<f32>>,
+    pending: Vec<f32>,
+    input_frames: usize,
+    output_frames: usize,
+}
+
+impl StreamConverter {
+    pub fn new(sample_rate: u32, channels: usize, downmix: DownmixStrategy) -> Result<Self> {
+        let ratio = TARGET_SAMPLE_RATE as f64 / sample_rate as f64;
+
+        let resampler = if sample_rate == TARGET_SAMPLE_RATE {
+            None
+        } else {
+            let params = rubato::SincInterpolationParameters {
+                sinc_len: 128,
+                f_cutoff: 0.95,
+                interpolation: SincInterpolationType::Linear,
+                oversampling_factor: 256,
+                window: WindowFunction::BlackmanHarris2,
+            };
+            Some(SincFixedIn::<f32>::new(
+                ratio,
+                1.0,
+                params,
+                CHUNK_FRAMES,
+                1,
+            )?)
+        };
+        // No initial delay to drop: rubato's `output_delay` counts half the
+        // sinc window, but its first output frame already lines up with the
+        // first input frame
+
+        Ok(Self {
+            downmixer: Downmixer::new(channels, downmix),
+            ratio,
+            resampler,
+            pending: Vec::with_capacity(CHUNK_FRAMES),
+            input_frames: 0,
+            output_frames: 0,
+        })
+    }
+
+    /// Converts `data` and appends the finished 16 kHz mono samples to `out`.
+    pub fn process(&mut self, data: &[f32], out: &mut Vec<f32>) -> Result<()> {
+        let before = self.pending.len();
+        self.downmixer.process(data, &mut self.pending);
+        self.input_frames += self.pending.len() - before;
+
+        self.resample(out)
+    }
+
+    fn resample(&mut self, out: &mut Vec<f32>) -> Result<()> {
+        match self.resampler.as_mut() {
+            None => {
+                self.output_frames += self.pending.len();
+                out.append(&mut self.pending);
+            }
+            Some(resampler) => {
+                while self.pending.len() >= CHUNK_FRAMES {
+                    let resampled = resampler.process(&[&self.pending[..CHUNK_FRAMES]], None)?;
+                    self.pending.drain(..CHUNK_FRAMES);
+                    emit(&resampled[0], &mut self.output_frames, out);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pushes the buffered tail through the resampler. `out` must be the
+    /// buffer every previous call appended to, as it is trimmed to length.
+    pub fn flush(&mut self, out: &mut Vec<f32>) -> Result<()> {
+        let before = self.pending.len();
+        self.downmixer.flush(&mut self.pending);
+        self.input_frames += self.pending.len() - before;
+        self.resample(out)?;
+
+        let Some(resampler) = self.resampler.as_mut() else {
+            return Ok(());
+        };
+
+        if !self.pending.is_empty() {
+            let resampled = resampler.process_partial(Some(&[&self.pending[..]]), None)?;
+            self.pending.clear();
+            emit(&resampled[0], &mut self.output_frames, out);
+        }
+
+        let expected = (self.input_frames as f64 * self.ratio) as usize;
+        while self.output_frames < expected {
+            let resampled = resampler.process_partial(None::<&[Vec<f32>]>, None)?;
+            if resampled[0].is_empty() {
+                break;
+            }
+            emit(&resampled[0], &mut self.output_frames, out);
+        }
+
+        if self.output_frames > expected {
+            let excess = self.output_frames - expected;
+            out.truncate(out.len().saturating_sub(excess));
+            self.output_frames = expected;
+        }
+
+        Ok(())
+    }
+}
+
+/// Appends resampled output.
+fn emit(samples: &[f32], output_frames: &mut usize, out: &mut Vec<f32>) {
+    out.extend_from_slice(samples);
+    *output_frames += samples.len();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    /// Interleaved stereo of a 440 Hz tone, the same on both channels.
+    fn stereo_tone(sample_rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let sample = (2.0 * PI * 440.0 * i as f32 / sample_rate as f32).sin() * 0.5;
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    /// Converts `data` fed in pieces of `piece` samples, then flushes.
+    fn convert(converter: &mut StreamConverter, data: &[f32], piece: usize) -> Vec<f32> {
+        let mut out = Vec::new();
+        for chunk in data.chunks(piece) {
+            converter.process(chunk, &mut out).expect("process");
+        }
+        converter.flush(&mut out).expect("flush");
+        out
+    }
+
+    fn converter(sample_rate: u32) -> StreamConverter {
+        StreamConverter::new(sample_rate, 2, DownmixStrategy::Average).expect("converter")
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn flush_leaves_the_converted_length() {
+        let input = stereo_tone(48000, 72000);
+        let out = convert(&mut converter(48000), &input, 4096);
+        assert_eq!(out.len(), 24000);
+        // The level of the tone survives, 0.5 / sqrt(2)
+        assert!((rms(&out[1000..23000]) - 0.3536).abs() < 0.005);
+    }
+
+    #[test]
+    fn output_lines_up_with_the_input() {
+        let mut click = vec![0.0; 2 * 48000];
+        click[2 * 30000] = 1.0;
+        click[2 * 30000 + 1] = 1.0;
+        let out = convert(&mut converter(48000), &click, 4096);
+
+        let peak = (0..out.len())
+            .max_by(|&a, &b| out[a].abs().total_cmp(&out[b].abs()))
+            .expect("output");
+        assert!(peak.abs_diff(10000) <= 1, "click at {}", peak);
+    }
+
+    #[test]
+    fn input_may_split_mid_frame() {
+        let input = stereo_tone(44100, 30000);
+        let whole = convert(&mut converter(44100), &input, input.len());
+        let split = convert(&mut converter(44100), &input, 1001);
+        assert_eq!(whole.len(), 30000 * 16000 / 44100);
+        assert_eq!(whole, split);
+    }
+
+    #[test]
+    fn target_rate_is_only_downmixed() {
+        let input = [0.5, -0.5, 0.25, 0.75, 1.0];
+        let mut converter = converter(TARGET_SAMPLE_RATE);
+        let mut out = Vec::new();
+        converter.process(&input[..3], &mut out).expect("process");
+        converter.process(&input[3..], &mut out).expect("process");
+        converter.flush(&mut out).expect("flush");
+        assert_eq!(out, [0.0, 0.5]);
+    }
+}
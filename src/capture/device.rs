@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, Host, SampleFormat, SupportedStreamConfig, SupportedStreamConfigRange};
+
+use super::source::{AudioSource, ErrorReporter, SampleSink, SourceFormat, StreamGuard};
+use crate::config::CaptureConfig;
+use crate::error::SpeakError;
+
+pub struct DeviceInfo {
+    pub host: String,
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<SupportedStreamConfigRange>,
+}
+
+/// Lists the input devices of every available audio host.
+pub fn list_input_devices() -> Result<Vec<DeviceInfo>> {
+    let mut devices = Vec::new();
+
+    for host_id in cpal::available_hosts() {
+        let host = match cpal::host_from_id(host_id) {
+            Ok(host) => host,
+            Err(e) => {
+                log::warn!("audio host {} is unavailable: {}", host_id.name(), e);
+                continue;
+            }
+        };
+        let default_name = host.default_input_device().and_then(|d| d.name().ok());
+
+        let inputs = host
+            .input_devices()
+            .with_context(|| format!("Failed to list devices of {}", host_id.name()))?;
+        for device in inputs {
+            let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+            let configs = device
+                .supported_input_configs()
+                .map(|configs| configs.collect())
+                .unwrap_or_default();
+
+            devices.push(DeviceInfo {
+                host: host_id.name().to_string(),
+                is_default: default_name.as_deref() == Some(name.as_str()),
+                name,
+                configs,
+            });
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Opens the input device named in `config`, falling back to the host default.
+///
+/// When the configured host or device cannot be used, the fallback device is
+/// returned together with a `DeviceNotFound` explaining why.
+pub fn resolve_input_device(
+    config: &CaptureConfig,
+) -> std::result::Result<(Device, Option<SpeakError>), SpeakError> {
+    let mut missing = Vec::new();
+
+    let host = match config.host.as_deref() {
+        Some(name) => match find_host(name) {
+            Some(host) => host,
+            None => {
+                missing.push(format!("Audio host \"{}\"", name));
+                cpal::default_host()
+            }
+        },
+        None => cpal::default_host(),
+    };
+
+    if let Some(name) = config.device.as_deref() {
+        match find_input_device(&host, name) {
+            Some(device) => {
+                let warning = fallback_warning(missing, &device);
+                return Ok((device, warning));
+            }
+            None => missing.push(format!("Input device \"{}\"", name)),
+        }
+    }
+
+    let device = host
+        .default_input_device()
+        .ok_or_else(|| SpeakError::DeviceUnavailable("no input device available".to_string()))?;
+    let warning = fallback_warning(missing, &device);
+
+    Ok((device, warning))
+}
+
+fn find_host(name: &str) -> Option<Host> {
+    let host_id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))?;
+    cpal::host_from_id(host_id).ok()
+}
+
+/// Prefers an exact name match, then the first device whose name contains
+/// `name` ignoring case.
+fn find_input_device(host: &Host, name: &str) -> Option<Device> {
+    let devices: Vec<(String, Device)> = host
+        .input_devices()
+        .ok()?
+        .filter_map(|d| Some((d.name().ok()?, d)))
+        .collect();
+
+    let needle = name.to_lowercase();
+    let index = devices.iter().position(|(n, _)| n == name).or_else(|| {
+        devices
+            .iter()
+            .position(|(n, _)| n.to_lowercase().contains(&needle))
+    })?;
+
+    devices.into_iter().nth(index).map(|(_, d)| d)
+}
+
+fn fallback_warning(missing: Vec<String>, device: &Device) -> Option<SpeakError> {
+    if missing.is_empty() {
+        return None;
+    }
+
+    Some(SpeakError::DeviceNotFound {
+        requested: missing.join(", "),
+        fallback: device.name().unwrap_or_else(|_| "<unknown>".to_string()),
+    })
+}
+
+/// Live input from a cpal device.
+pub struct CpalSource {
+    config: CaptureConfig,
+    input: Option<(Device, SupportedStreamConfig)>,
+}
+
+impl CpalSource {
+    pub fn new(config: CaptureConfig) -> Self {
+        Self {
+            config,
+            input: None,
+        }
+    }
+}
+
+impl AudioSource for CpalSource {
+    fn open(&mut self, errors: &ErrorReporter) -> std::result::Result<SourceFormat, SpeakError> {
+        let (device, warning) = resolve_input_device(&self.config)?;
+        if let Some(warning) = warning {
+            errors.warn(warning);
+        }
+
+        let config = device
+            .default_input_config()
+            .map_err(|e| SpeakError::DeviceUnavailable(format!("no usable input config: {}", e)))?;
+        let format = SourceFormat {
+            sample_rate: config.sample_rate().0,
+            channels: config.channels() as usize,
+        };
+
+        self.input = Some((device, config));
+        Ok(format)
+    }
+
+    fn start(
+        &mut self,
+        mut sink: SampleSink,
+        errors: ErrorReporter,
+    ) -> std::result::Result<StreamGuard, SpeakError> {
+        let Some((device, config)) = self.input.take() else {
+            return Err(SpeakError::DeviceUnavailable(
+                "device was not opened".to_string(),
+            ));
+        };
+
+        // Stream errors arrive on the audio backend's thread; the drain loop
+        // sees recording stop and tears the stream down
+        let err_fn = move |err: cpal::StreamError| {
+            let error = match err {
+                cpal::StreamError::DeviceNotAvailable => {
+                    SpeakError::DeviceUnavailable("device was disconnected".to_string())
+                }
+                err => SpeakError::StreamError(err.to_string()),
+            };
+            errors.fail(error);
+        };
+
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => device.build_input_stream(
+                &config.into(),
+                move |data: &[f32], _: &_| {
+                    sink.push(data.iter().copied(), data.len());
+                },
+                err_fn,
+                None,
+            ),
+            SampleFormat::I16 => device.build_input_stream(
+                &config.into(),
+                move |data: &[i16], _: &_| {
+                    sink.push(data.iter().map(|&s| s as f32 / 32768.0), data.len());
+                },
+                err_fn,
+                None,
+            ),
+            SampleFormat::U16 => device.build_input_stream(
+                &config.into(),
+                move |data: &[u16], _: &_| {
+                    sink.push(data.iter().map(|&s| (s as f32 / 32768.0) - 1.0), data.len());
+                },
+                err_fn,
+                None,
+            ),
+            format => return Err(SpeakError::UnsupportedFormat(format.to_string())),
+        }
+        .map_err(|e| SpeakError::StreamError(format!("failed to build input stream: {}", e)))?;
+
+        stream
+            .play()
+            .map_err(|e| SpeakError::StreamError(format!("failed to start stream: {}", e)))?;
+
+        Ok(Box::new(stream))
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use super::AudioBuffer;
+
+/// Decodes the first audio track of a WAV, FLAC, MP3 or OGG file into
+/// interleaved f32 samples at the file's native rate.
+pub fn decode_file(path: &Path) -> Result<AudioBuffer> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let extension = path.extension().and_then(|e| e.to_str());
+
+    decode(Box::new(file), extension)
+        .with_context(|| format!("Failed to decode {}", path.display()))
+}
+
+/// Decodes an in-memory audio file, such as an upload. `extension` is only a
+/// hint; the container is detected from its contents.
+pub fn decode_bytes(bytes: Vec<u8>, extension: Option<&str>) -> Result<AudioBuffer> {
+    decode(Box::new(Cursor::new(bytes)), extension)
+}
+
+fn decode(source: Box<dyn MediaSource>, extension: Option<&str>) -> Result<AudioBuffer> {
+    let stream = MediaSourceStream::new(source, Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = extension {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .context("Unsupported audio format")?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .context("No audio track found")?;
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut channels = track.codec_params.channels.map(|c| c.count());
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .context("Unsupported audio codec")?;
+
+    let mut data = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(e).context("Failed to read audio packet"),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(e)) => {
+                log::warn!("skipping undecodable packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e).context("Failed to decode audio"),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate = Some(spec.rate);
+        channels = Some(spec.channels.count());
+
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+        data.extend_from_slice(samples.samples());
+    }
+
+    Ok(AudioBuffer {
+        data,
+        sample_rate: sample_rate.context("Unknown sample rate")?,
+        channels: channels.context("Unknown channel count")?,
+    })
+}
+
+/// Encodes mono samples as a 16-bit PCM WAV file, for tests that need
+/// audio on disk or in an upload.
+#[cfg(test)]
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_wav() {
+        let samples: Vec<f32> = (0..16000).map(|i| (i % 100) as f32 / 200.0).collect();
+        let audio = decode_bytes(encode_wav(&samples, 16000), Some("wav")).expect("decode");
+        assert_eq!(audio.sample_rate, 16000);
+        assert_eq!(audio.channels, 1);
+        assert_eq!(audio.data.len(), samples.len());
+        assert!((audio.data[42] - samples[42]).abs() < 0.001);
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        assert!(decode_bytes(b"not audio".to_vec(), None).is_err());
+    }
+}
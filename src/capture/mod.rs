@@ -1,37 +1,99 @@
 #![allow(dead_code)]
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-use cpal::SampleFormat;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rtrb::{Consumer, RingBuffer};
 
+use crate::config::CaptureConfig;
+use crate::error::SpeakError;
+
+mod convert;
+mod device;
+mod file;
+mod playback;
+mod source;
+
+pub use device::list_input_devices;
+#[cfg(test)]
+pub use file::encode_wav;
+pub use file::{decode_bytes, decode_file};
+pub use source::{AudioSource, ErrorReporter, SampleSink, SourceConfig, SourceFactory};
+
+pub struct AudioBuffer {
+    pub data: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: usize,
+}
+
+impl AudioBuffer {
+    pub fn duration(&self) -> f32 {
+        self.data.len() as f32 / (self.sample_rate as f32 * self.channels as f32)
+    }
+}
+
+/// Seconds of audio the ring buffer between the device callback and the
+/// consumer thread can hold before samples are dropped.
+const RING_SECONDS: usize = 2;
+const DRAIN_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Captures audio without ever blocking the realtime callback.
+///
+/// The `AudioSource` (a device callback, or a playback thread for files and
+/// test signals) only pushes samples into a lock-free SPSC ring buffer.
+/// The recording thread drains it, converts the audio to 16 kHz mono as it
+/// goes and appends the result to `audio_data`, advancing `sample_count` so
+/// duration queries never touch the sample buffer.
 pub struct SimpleAudioCapture {
     audio_data: Arc<Mutex<Option<Vec<f32>>>>,
-    sample_rate: Arc<Mutex<Option<u32>>>,
-    channels: Arc<Mutex<Option<usize>>>,
+    sample_count: Arc<AtomicUsize>,
+    sample_rate: Arc<AtomicU32>,
+    channels: Arc<AtomicUsize>,
     is_recording: Arc<AtomicBool>,
     recording_thread: Arc<Mutex<Option<JoinHandle<()>>>>,
+    error: Arc<Mutex<Option<SpeakError>>>,
+    source: SourceFactory,
+    config: CaptureConfig,
 }
 
 impl SimpleAudioCapture {
-    pub fn new() -> Self {
+    pub fn new(config: CaptureConfig) -> Self {
+        let source = source::factory(&config);
+        Self::with_source(config, source)
+    }
+
+    /// Records from sources made by `source` instead of the one selected in
+    /// `config`.
+    pub fn with_source(config: CaptureConfig, source: SourceFactory) -> Self {
         let audio_data = Arc::new(Mutex::new(Some(Vec::new())));
-        let sample_rate = Arc::new(Mutex::new(None));
-        let channels = Arc::new(Mutex::new(None));
+        let sample_count = Arc::new(AtomicUsize::new(0));
+        let sample_rate = Arc::new(AtomicU32::new(0));
+        let channels = Arc::new(AtomicUsize::new(0));
         let is_recording = Arc::new(AtomicBool::new(false));
         let recording_thread = Arc::new(Mutex::new(None));
+        let error = Arc::new(Mutex::new(None));
 
         Self {
             audio_data,
+            sample_count,
             sample_rate,
             channels,
             is_recording,
             recording_thread,
+            error,
+            source,
+            config,
         }
     }
 
+    /// Returns the last capture problem, if any, and clears it. When the
+    /// error stops recording, `get_is_recording` is already false.
+    pub fn take_error(&self) -> Option<SpeakError> {
+        self.error.lock().ok()?.take()
+    }
+
     pub fn get_is_recording(&self) -> bool {
         self.is_recording.load(Ordering::SeqCst)
     }
@@ -44,119 +106,75 @@ impl SimpleAudioCapture {
         self.is_recording.store(true, Ordering::SeqCst);
 
         let audio_data_clone = self.audio_data.clone();
+        let sample_count_clone = self.sample_count.clone();
         let sample_rate_clone = self.sample_rate.clone();
         let channels_clone = self.channels.clone();
         let is_recording_clone = self.is_recording.clone();
+        let errors = ErrorReporter::new(self.error.clone(), self.is_recording.clone());
+        let source = self.source.clone();
+        let downmix = self.config.downmix;
 
         let handle = thread::spawn(move || {
-            // Initialize audio input device
-            let host = cpal::default_host();
-            let device = match host.default_input_device() {
-                Some(d) => d,
-                None => {
-                    eprintln!("No input device available");
-                    is_recording_clone.store(false, Ordering::SeqCst);
-                    return;
-                }
-            };
+            let mut source = source();
 
-            let config = match device.default_input_config() {
-                Ok(c) => c,
-                Err(e) => {
-                    eprintln!("Failed to get default input config: {}", e);
-                    is_recording_clone.store(false, Ordering::SeqCst);
-                    return;
-                }
+            let format = match source.open(&errors) {
+                Ok(format) => format,
+                Err(e) => return errors.fail(e),
             };
 
-            let sample_rate_value = config.sample_rate().0;
-            let channels_value = config.channels() as usize;
+            log::debug!(
+                "capturing {} Hz, {} channels, converting to {} Hz mono",
+                format.sample_rate,
+                format.channels,
+                convert::TARGET_SAMPLE_RATE
+            );
 
-            if let Ok(mut sr) = sample_rate_clone.lock() {
-                *sr = Some(sample_rate_value);
-            }
-            if let Ok(mut ch) = channels_clone.lock() {
-                *ch = Some(channels_value);
-            }
+            let mut converter =
+                match convert::StreamConverter::new(format.sample_rate, format.channels, downmix) {
+                    Ok(c) => c,
+                    Err(e) => return errors.fail(SpeakError::Resample(e.to_string())),
+                };
 
-            let err_fn = |err| eprintln!("An error occurred on the input audio stream: {}", err);
-
-            let stream_result = match config.sample_format() {
-                SampleFormat::F32 => {
-                    let audio_clone = audio_data_clone.clone();
-                    device.build_input_stream(
-                        &config.into(),
-                        move |data: &[f32], _: &_| {
-                            if let Ok(mut audio_buffer) = audio_clone.lock() {
-                                if let Some(buffer) = audio_buffer.as_mut() {
-                                    buffer.extend_from_slice(data);
-                                }
-                            }
-                        },
-                        err_fn,
-                        None,
-                    )
-                }
-                SampleFormat::I16 => {
-                    let audio_clone = audio_data_clone.clone();
-                    device.build_input_stream(
-                        &config.into(),
-                        move |data: &[i16], _: &_| {
-                            if let Ok(mut audio_buffer) = audio_clone.lock() {
-                                if let Some(buffer) = audio_buffer.as_mut() {
-                                    buffer.extend(data.iter().map(|&s| s as f32 / 32768.0));
-                                }
-                            }
-                        },
-                        err_fn,
-                        None,
-                    )
-                }
-                SampleFormat::U16 => {
-                    let audio_clone = audio_data_clone.clone();
-                    device.build_input_stream(
-                        &config.into(),
-                        move |data: &[u16], _: &_| {
-                            if let Ok(mut audio_buffer) = audio_clone.lock() {
-                                if let Some(buffer) = audio_buffer.as_mut() {
-                                    buffer
-                                        .extend(data.iter().map(|&s| ((s as f32 / 32768.0) - 1.0)));
-                                }
-                            }
-                        },
-                        err_fn,
-                        None,
-                    )
-                }
-                _ => {
-                    eprintln!("Unsupported sample format");
-                    is_recording_clone.store(false, Ordering::SeqCst);
-                    return;
-                }
-            };
+            sample_rate_clone.store(convert::TARGET_SAMPLE_RATE, Ordering::SeqCst);
+            channels_clone.store(1, Ordering::SeqCst);
+
+            let (producer, mut consumer) = RingBuffer::<f32>::new(
+                format.sample_rate as usize * format.channels * RING_SECONDS,
+            );
+            let dropped = Arc::new(AtomicUsize::new(0));
 
-            // The stream is kept locally in this thread
-            let _stream = match stream_result {
-                Ok(s) => {
-                    if let Err(e) = s.play() {
-                        eprintln!("Failed to play stream: {}", e);
-                        is_recording_clone.store(false, Ordering::SeqCst);
-                        return;
-                    }
-                    Some(s)
-                }
-                Err(e) => {
-                    eprintln!("Failed to build input stream: {}", e);
-                    is_recording_clone.store(false, Ordering::SeqCst);
-                    return;
-                }
+            let sink = SampleSink::new(producer, dropped.clone());
+            let stream = match source.start(sink, errors.clone()) {
+                Ok(stream) => stream,
+                Err(e) => return errors.fail(e),
             };
 
-            // Keep thread alive while recording
+            // Drain the ring buffer while recording
             while is_recording_clone.load(Ordering::SeqCst) {
-                std::thread::sleep(std::time::Duration::from_millis(100));
+                drain_samples(
+                    &mut consumer,
+                    &mut converter,
+                    &audio_data_clone,
+                    &sample_count_clone,
+                    false,
+                );
+                thread::sleep(DRAIN_INTERVAL);
+            }
+
+            // Stop the callback before collecting what it pushed last
+            drop(stream);
+            drain_samples(
+                &mut consumer,
+                &mut converter,
+                &audio_data_clone,
+                &sample_count_clone,
+                true,
+            );
+
+            let dropped = dropped.load(Ordering::SeqCst);
+            if dropped > 0 {
+                log::warn!("dropped {} samples, ring buffer overrun", dropped);
             }
-            // _stream is dropped when this thread ends
         });
 
         if let Ok(mut thread_guard) = self.recording_thread.lock() {
@@ -182,28 +200,36 @@ impl SimpleAudioCapture {
         }
     }
 
+    /// Returns the samples recorded after the first `offset` samples.
+    pub fn get_audio_since(&self, offset: usize) -> Option<Vec<f32>> {
+        let data = self.audio_data.lock().ok()?;
+        data.as_ref()
+            .map(|d| d.get(offset..).map(<[f32]>::to_vec).unwrap_or_default())
+    }
+
     pub fn get_sample_rate(&self) -> Option<u32> {
-        if let Ok(rate) = self.sample_rate.lock() {
-            *rate
-        } else {
-            None
+        match self.sample_rate.load(Ordering::SeqCst) {
+            0 => None,
+            rate => Some(rate),
         }
     }
 
     pub fn get_channels(&self) -> Option<usize> {
-        if let Ok(ch) = self.channels.lock() {
-            *ch
-        } else {
-            None
+        match self.channels.load(Ordering::SeqCst) {
+            0 => None,
+            ch => Some(ch),
         }
     }
 
+    pub fn get_sample_count(&self) -> usize {
+        self.sample_count.load(Ordering::SeqCst)
+    }
+
     pub fn get_duration(&self) -> Option<f32> {
-        let audio_data = self.get_audio_data()?;
         let sample_rate = self.get_sample_rate()? as f32;
         let channels = self.get_channels()? as f32;
 
-        let num_samples = audio_data.len() as f32;
+        let num_samples = self.get_sample_count() as f32;
         let duration = num_samples / (sample_rate * channels);
 
         Some(duration)
@@ -221,5 +247,44 @@ impl SimpleAudioCapture {
         if let Ok(mut data) = self.audio_data.lock() {
             *data = Some(Vec::new());
         }
+        self.sample_count.store(0, Ordering::SeqCst);
+    }
+}
+
+fn drain_samples(
+    consumer: &mut Consumer<f32>,
+    converter: &mut convert::StreamConverter,
+    audio_data: &Mutex<Option<Vec<f32>>>,
+    sample_count: &AtomicUsize,
+    flush: bool,
+) {
+    let available = consumer.slots();
+    if available == 0 && !flush {
+        return;
+    }
+
+    let Ok(mut audio_buffer) = audio_data.lock() else {
+        return;
+    };
+    let Some(buffer) = audio_buffer.as_mut() else {
+        return;
+    };
+
+    if let Ok(chunk) = consumer.read_chunk(available) {
+        let (first, second) = chunk.as_slices();
+        for data in [first, second] {
+            if let Err(e) = converter.process(data, buffer) {
+                log::error!("failed to convert captured audio: {}", e);
+            }
+        }
+        chunk.commit_all();
     }
+
+    if flush {
+        if let Err(e) = converter.flush(buffer) {
+            log::error!("failed to flush captured audio: {}", e);
+        }
+    }
+
+    sample_count.store(buffer.len(), Ordering::SeqCst);
 }
@@ -0,0 +1,216 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::convert::TARGET_SAMPLE_RATE;
+use super::source::{AudioSource, ErrorReporter, SampleSink, SourceFormat, StreamGuard};
+use super::{AudioBuffer, decode_file};
+use crate::error::SpeakError;
+
+type Result<T> = std::result::Result<T, SpeakError>;
+
+/// How often a playback thread hands samples to the sink.
+const PLAYBACK_INTERVAL: Duration = Duration::from_millis(10);
+const SINE_AMPLITUDE: f32 = 0.5;
+const NOISE_SEED: u32 = 0x2545_f491;
+
+/// Plays a decoded audio file into the recording, then silence.
+pub struct FileSource {
+    path: PathBuf,
+    speed: f32,
+    audio: Option<AudioBuffer>,
+}
+
+impl FileSource {
+    pub fn new(path: PathBuf, speed: f32) -> Self {
+        let speed = if speed > 0.0 {
+            speed
+        } else {
+            log::warn!("playback speed must be positive, using 1.0");
+            1.0
+        };
+
+        Self {
+            path,
+            speed,
+            audio: None,
+        }
+    }
+}
+
+impl AudioSource for FileSource {
+    fn open(&mut self, _errors: &ErrorReporter) -> Result<SourceFormat> {
+        let audio = decode_file(&self.path).map_err(|e| SpeakError::SourceFile {
+            path: self.path.clone(),
+            reason: format!("{:#}", e),
+        })?;
+        log::debug!(
+            "playing {} ({:.1}s) at {}x speed",
+            self.path.display(),
+            audio.duration(),
+            self.speed
+        );
+
+        let format = SourceFormat {
+            sample_rate: audio.sample_rate,
+            channels: audio.channels,
+        };
+        self.audio = Some(audio);
+        Ok(format)
+    }
+
+    fn start(&mut self, sink: SampleSink, _errors: ErrorReporter) -> Result<StreamGuard> {
+        let Some(audio) = self.audio.take() else {
+            return Err(SpeakError::SourceFile {
+                path: self.path.clone(),
+                reason: "source was not opened".to_string(),
+            });
+        };
+        let format = SourceFormat {
+            sample_rate: audio.sample_rate,
+            channels: audio.channels,
+        };
+
+        let mut position = 0;
+        let fill = move |out: &mut [f32]| {
+            let available = (audio.data.len() - position).min(out.len());
+            out[..available].copy_from_slice(&audio.data[position..position + available]);
+            out[available..].fill(0.0);
+            position += available;
+        };
+
+        Ok(play(format, self.speed, fill, sink))
+    }
+}
+
+enum Signal {
+    Sine { frequency: f32 },
+    Silence,
+    Noise { amplitude: f32 },
+}
+
+/// Generated 16 kHz mono audio, delivered in real time.
+pub struct SignalSource {
+    signal: Signal,
+}
+
+impl SignalSource {
+    pub fn sine(frequency: f32) -> Self {
+        Self {
+            signal: Signal::Sine { frequency },
+        }
+    }
+
+    pub fn silence() -> Self {
+        Self {
+            signal: Signal::Silence,
+        }
+    }
+
+    pub fn noise(amplitude: f32) -> Self {
+        Self {
+            signal: Signal::Noise { amplitude },
+        }
+    }
+
+    fn format() -> SourceFormat {
+        SourceFormat {
+            sample_rate: TARGET_SAMPLE_RATE,
+            channels: 1,
+        }
+    }
+}
+
+impl AudioSource for SignalSource {
+    fn open(&mut self, _errors: &ErrorReporter) -> Result<SourceFormat> {
+        Ok(Self::format())
+    }
+
+    fn start(&mut self, sink: SampleSink, _errors: ErrorReporter) -> Result<StreamGuard> {
+        let guard = match self.signal {
+            Signal::Sine { frequency } => {
+                let step = frequency / TARGET_SAMPLE_RATE as f32;
+                let mut phase = 0.0_f32;
+                let fill = move |out: &mut [f32]| {
+                    for sample in out {
+                        *sample = SINE_AMPLITUDE * (phase * std::f32::consts::TAU).sin();
+                        phase = (phase + step).fract();
+                    }
+                };
+                play(Self::format(), 1.0, fill, sink)
+            }
+            Signal::Silence => play(Self::format(), 1.0, |out: &mut [f32]| out.fill(0.0), sink),
+            Signal::Noise { amplitude } => {
+                // xorshift32, so every run produces the same samples
+                let mut state = NOISE_SEED;
+                let fill = move |out: &mut [f32]| {
+                    for sample in out {
+                        state ^= state << 13;
+                        state ^= state >> 17;
+                        state ^= state << 5;
+                        *sample = amplitude * (state as f32 / u32::MAX as f32 * 2.0 - 1.0);
+                    }
+                };
+                play(Self::format(), 1.0, fill, sink)
+            }
+        };
+
+        Ok(guard)
+    }
+}
+
+/// Stops the playback thread when dropped.
+struct Playback {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Feeds `fill` into `sink` at `speed` times real time. Playback never runs
+/// ahead of what the ring buffer can take, so no samples are dropped; a
+/// consumer that falls behind only slows it down.
+fn play<F>(format: SourceFormat, speed: f32, mut fill: F, mut sink: SampleSink) -> StreamGuard
+where
+    F: FnMut(&mut [f32]) + Send + 'static,
+{
+    let running = Arc::new(AtomicBool::new(true));
+    let channels = format.channels.max(1);
+    let samples_per_sec = format.sample_rate as f64 * channels as f64 * speed as f64;
+
+    let thread = {
+        let running = running.clone();
+        thread::spawn(move || {
+            let started = Instant::now();
+            let mut delivered = 0;
+            let mut buffer = Vec::new();
+
+            while running.load(Ordering::SeqCst) {
+                let due = (started.elapsed().as_secs_f64() * samples_per_sec) as usize;
+                let len = due.saturating_sub(delivered).min(sink.free());
+                let len = len - len % channels;
+                if len > 0 {
+                    buffer.resize(len, 0.0);
+                    fill(&mut buffer);
+                    sink.push(buffer.iter().copied(), len);
+                    delivered += len;
+                }
+                thread::sleep(PLAYBACK_INTERVAL);
+            }
+        })
+    };
+
+    Box::new(Playback {
+        running,
+        thread: Some(thread),
+    })
+}
@@ -0,0 +1,145 @@
+use std::any::Any;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rtrb::Producer;
+
+use crate::config::CaptureConfig;
+use crate::error::SpeakError;
+
+type Result<T> = std::result::Result<T, SpeakError>;
+
+/// Where recorded audio comes from.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceConfig {
+    /// The input device selected by `host` and `device`
+    #[default]
+    Device,
+    /// Plays an audio file as if it were spoken into the microphone, then
+    /// keeps delivering silence. `speed` above 1 plays it accelerated.
+    File {
+        path: PathBuf,
+        #[serde(default = "default_speed")]
+        speed: f32,
+    },
+    /// A sine tone at the given frequency in Hz
+    Sine(f32),
+    Silence,
+    /// White noise at the given amplitude, the same on every run
+    Noise(f32),
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+/// Sample rate and channel count of the interleaved samples a source delivers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceFormat {
+    pub sample_rate: u32,
+    pub channels: usize,
+}
+
+/// Keeps a started source delivering samples until it is dropped.
+pub type StreamGuard = Box<dyn Any>;
+
+/// Produces interleaved f32 samples for a recording.
+///
+/// A source is created for each recording and used from the capture thread
+/// only: `open` once, then `start`.
+pub trait AudioSource {
+    /// Prepares the source and returns the format it will deliver.
+    fn open(&mut self, errors: &ErrorReporter) -> Result<SourceFormat>;
+
+    /// Starts pushing samples into `sink` until the returned guard is
+    /// dropped. Failures after this point go to `errors`.
+    fn start(&mut self, sink: SampleSink, errors: ErrorReporter) -> Result<StreamGuard>;
+}
+
+/// Creates the source for each recording.
+pub type SourceFactory = Arc<dyn Fn() -> Box<dyn AudioSource> + Send + Sync>;
+
+/// The factory for the source selected in `config`.
+pub fn factory(config: &CaptureConfig) -> SourceFactory {
+    let config = config.clone();
+    Arc::new(move || -> Box<dyn AudioSource> {
+        match &config.source {
+            SourceConfig::Device => Box::new(super::device::CpalSource::new(config.clone())),
+            SourceConfig::File { path, speed } => {
+                Box::new(super::playback::FileSource::new(path.clone(), *speed))
+            }
+            SourceConfig::Sine(frequency) => {
+                Box::new(super::playback::SignalSource::sine(*frequency))
+            }
+            SourceConfig::Silence => Box::new(super::playback::SignalSource::silence()),
+            SourceConfig::Noise(amplitude) => {
+                Box::new(super::playback::SignalSource::noise(*amplitude))
+            }
+        }
+    })
+}
+
+/// The writing end of the ring buffer the capture thread drains.
+pub struct SampleSink {
+    producer: Producer<f32>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl SampleSink {
+    pub(super) fn new(producer: Producer<f32>, dropped: Arc<AtomicUsize>) -> Self {
+        Self { producer, dropped }
+    }
+
+    /// How many samples can be pushed without dropping any.
+    pub fn free(&self) -> usize {
+        self.producer.slots()
+    }
+
+    /// Copies `len` samples into the ring buffer without locking or
+    /// allocating, so it is safe to call from a realtime audio callback.
+    /// Whatever does not fit is dropped and counted.
+    pub fn push<I>(&mut self, samples: I, len: usize)
+    where
+        I: Iterator<Item = f32>,
+    {
+        let writable = len.min(self.producer.slots());
+        if let Ok(chunk) = self.producer.write_chunk_uninit(writable) {
+            chunk.fill_from_iter(samples);
+        }
+        if writable < len {
+            self.dropped.fetch_add(len - writable, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Hands capture problems to the controller through `take_error`.
+#[derive(Clone)]
+pub struct ErrorReporter {
+    slot: Arc<Mutex<Option<SpeakError>>>,
+    is_recording: Arc<AtomicBool>,
+}
+
+impl ErrorReporter {
+    pub(super) fn new(slot: Arc<Mutex<Option<SpeakError>>>, is_recording: Arc<AtomicBool>) -> Self {
+        Self { slot, is_recording }
+    }
+
+    /// Records a problem that recording continues through.
+    pub fn warn(&self, error: SpeakError) {
+        log::warn!("{}", error);
+        if let Ok(mut slot) = self.slot.lock() {
+            *slot = Some(error);
+        }
+    }
+
+    /// Records an error that ends the recording and stops the capture thread.
+    pub fn fail(&self, error: SpeakError) {
+        log::error!("{}", error);
+        if let Ok(mut slot) = self.slot.lock() {
+            *slot = Some(error);
+        }
+        self.is_recording.store(false, Ordering::SeqCst);
+    }
+}
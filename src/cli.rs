@@ -0,0 +1,259 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::{Parser, Subcommand};
+use log::info;
+
+use crate::{
+    capture,
+    config::AppConfig,
+    control,
+    control::ControlCommand,
+    downmix::{DownmixStrategy, downmix},
+    export::{self, ExportFormat},
+    postprocess::{self, Pipeline},
+    whisper,
+};
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Start recording right away, or toggle recording in the running instance
+    #[arg(long)]
+    pub toggle: bool,
+
+    /// Run without a window, controlled through `ctl` and printing results to stdout
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Apply the settings of `[profiles.<PROFILE>]` from the config file
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Transcribe audio files (WAV, FLAC, MP3, OGG) and print the text to stdout
+    Transcribe {
+        /// Audio files to transcribe
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Text)]
+        format: ExportFormat,
+
+        /// Write each result to `<name>.<ext>` in this directory instead of printing it
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+    },
+    /// List audio input devices and their supported configurations
+    Devices,
+    /// Serve an OpenAI-compatible transcription API over HTTP
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+    /// Control the running instance through its control socket
+    Ctl {
+        #[arg(value_enum)]
+        command: ControlCommand,
+    },
+}
+
+/// Transcribes each file in turn. A file that fails is reported and skipped;
+/// the error at the end says how many did.
+pub fn transcribe(
+    config: AppConfig,
+    profile: Option<&str>,
+    files: &[PathBuf],
+    format: ExportFormat,
+    output_dir: Option<&Path>,
+) -> Result<()> {
+    let downmix_strategy = config.capture.downmix;
+    let mut pipeline = Pipeline::new(&config, profile)?;
+    let task = config.whisper.task;
+    let transcriber =
+        whisper::load(config.whisper).inspect_err(|e| log::error!("{}", e.recovery()))?;
+
+    let mut failed = 0;
+    for file in files {
+        if let Err(e) = transcribe_file(
+            transcriber.as_ref(),
+            &mut pipeline,
+            task,
+            file,
+            downmix_strategy,
+            format,
+            output_dir,
+        ) {
+            log::error!("{:#}", e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} files failed", failed, files.len());
+    }
+    Ok(())
+}
+
+fn transcribe_file(
+    transcriber: &dyn whisper::Transcriber,
+    pipeline: &mut Pipeline,
+    task: whisper::Task,
+    file: &Path,
+    downmix_strategy: DownmixStrategy,
+    format: ExportFormat,
+    output_dir: Option<&Path>,
+) -> Result<()> {
+    let audio = capture::decode_file(file)?;
+    info!(
+        "transcribing {} ({:.1}s, {} Hz, {} channels)",
+        file.display(),
+        audio.duration(),
+        audio.sample_rate,
+        audio.channels
+    );
+
+    let mono = downmix(&audio.data, audio.channels, downmix_strategy);
+    let mut transcription = transcriber
+        .transcribe(&whisper::InputAudio {
+            data: &mono,
+            sample_rate: audio.sample_rate,
+            channels: 1,
+        })
+        .with_context(|| format!("Failed to transcribe {}", file.display()))?;
+
+    // A whole file is a finished recording, rewritten like one
+    let language = match task {
+        whisper::Task::Translate => Some("en".to_string()),
+        whisper::Task::Transcribe => transcription.language.as_ref().map(|l| l.code.clone()),
+    };
+    let context = postprocess::Context {
+        language: language.as_deref(),
+        final_pass: true,
+    };
+    pipeline.process(&mut transcription, &context);
+
+    let output = export::export(format, &transcription.combined, &transcription.segments);
+    match output_dir {
+        Some(dir) => {
+            let stem = file.file_stem().unwrap_or(file.as_os_str());
+            let path = dir.join(format!("{}.{}", stem.to_string_lossy(), format.extension()));
+            std::fs::write(&path, output)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            info!("wrote {}", path.display());
+        }
+        None => write!(std::io::stdout().lock(), "{}", output)?,
+    }
+    Ok(())
+}
+
+pub fn devices() -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+
+    for device in capture::list_input_devices()? {
+        let marker = if device.is_default { " (default)" } else { "" };
+        writeln!(stdout, "[{}] {}{}", device.host, device.name, marker)?;
+
+        for config in &device.configs {
+            let min_rate = config.min_sample_rate().0;
+            let max_rate = config.max_sample_rate().0;
+            let rates = if min_rate == max_rate {
+                format!("{} Hz", min_rate)
+            } else {
+                format!("{}-{} Hz", min_rate, max_rate)
+            };
+            writeln!(
+                stdout,
+                "    {} ch, {}, {}",
+                config.channels(),
+                rates,
+                config.sample_format()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn ctl(command: ControlCommand) -> Result<()> {
+    let reply = control::send(command)?;
+    if !reply.is_empty() {
+        println!("{}", reply);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postprocess::ReplacementRule;
+    use crate::whisper::Backend;
+
+    #[test]
+    fn transcribe_continues_past_a_failing_file() {
+        let dir = std::env::temp_dir().join(format!("speak-rs-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let good = dir.join("good.wav");
+        let samples: Vec<f32> = (0..2 * 16000)
+            .map(|i| ((i as f32) * 0.05).sin() * 0.2)
+            .collect();
+        std::fs::write(&good, capture::encode_wav(&samples, 16000)).expect("write wav");
+        let missing = dir.join("missing.wav");
+
+        let mut config = AppConfig::default();
+        config.whisper.backend = Backend::Mock;
+        let result = transcribe(
+            config,
+            None,
+            &[missing, good],
+            ExportFormat::Text,
+            Some(dir.as_path()),
+        );
+
+        let error = result.expect_err("a file failed");
+        assert_eq!(error.to_string(), "1 of 2 files failed");
+        assert!(dir.join("good.txt").is_file());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn transcribe_applies_the_replacements() {
+        let dir = std::env::temp_dir().join(format!("speak-rs-cli-rules-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let file = dir.join("note.wav");
+        std::fs::write(&file, capture::encode_wav(&[0.1; 2 * 16000], 16000)).expect("write wav");
+        let script = dir.join("script.txt");
+        std::fs::write(&script, "Deploy it to cube cuddle.\n").expect("write script");
+
+        let mut config = AppConfig::default();
+        config.whisper.backend = Backend::Mock;
+        config.whisper.mock_script = Some(script);
+        config.replacements.rules.push(ReplacementRule {
+            from: "cube cuddle".to_string(),
+            to: "Kubernetes".to_string(),
+            regex: false,
+            whole_word: true,
+            preserve_case: false,
+        });
+        transcribe(
+            config,
+            None,
+            &[file],
+            ExportFormat::Text,
+            Some(dir.as_path()),
+        )
+        .expect("transcribed");
+
+        let text = std::fs::read_to_string(dir.join("note.txt")).expect("read output");
+        assert_eq!(text, "Deploy it to Kubernetes.\n");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,27 +1,57 @@
 use anyhow::{Context, Result};
-use config::{Config, Environment, File};
+use config::{Config, Environment, File, Map, Value, ValueKind};
 use directories::ProjectDirs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::capture::SourceConfig;
+use crate::downmix::DownmixStrategy;
+use crate::postprocess::{ReplacementRule, Stage};
+use crate::whisper::{Backend, Task, Vocabulary};
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct WhisperConfig {
+    /// Engine to transcribe with; `mock` replays `mock_script` instead of loading a model
+    #[serde(default)]
+    pub backend: Backend,
     pub model_path: PathBuf,
     pub use_gpu: bool,
+    /// Language code of the speech, or "auto" to detect it on every pass
     pub language: String,
+    /// "transcribe", or "translate" for English text from any language
+    #[serde(default)]
+    pub task: Task,
     pub audio_context: i32,
     pub no_speech_threshold: f32,
     pub num_threads: i32,
+    /// Time every word of a segment and report its probability
+    #[serde(default)]
+    pub word_timestamps: bool,
+    /// Text that precedes every recording, e.g. a sentence in the style to transcribe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_prompt: Option<String>,
+    /// Names and jargon to spell correctly: a list, or the path of a word file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vocabulary: Option<Vocabulary>,
+    /// Transcripts for the mock backend, one pass per line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mock_script: Option<PathBuf>,
 }
 
 impl Default for WhisperConfig {
     fn default() -> Self {
         Self {
+            backend: Backend::default(),
             model_path: PathBuf::from("models/ggml-small.en.bin"),
             use_gpu: true,
             language: "en".to_string(),
+            task: Task::default(),
             audio_context: 768,
             no_speech_threshold: 0.5,
             num_threads: 2,
+            word_timestamps: false,
+            initial_prompt: None,
+            vocabulary: None,
+            mock_script: None,
         }
     }
 }
@@ -32,6 +62,15 @@ pub struct BehaviorConfig {
     pub auto_copy: bool,
     pub stop_phrase_enabled: bool,
     pub stop_phrase_pattern: String,
+    /// Discards the recording when it ends with this phrase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancel_phrase_pattern: Option<String>,
+    /// Takes back the sentence said before this phrase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undo_phrase_pattern: Option<String>,
+    /// Stops like the stop phrase and sends the text to the output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submit_phrase_pattern: Option<String>,
 }
 
 impl Default for BehaviorConfig {
@@ -41,14 +80,129 @@ impl Default for BehaviorConfig {
             auto_copy: true,
             stop_phrase_enabled: true,
             stop_phrase_pattern: r"(?i)that'?s all\.?$".to_string(),
+            cancel_phrase_pattern: None,
+            undo_phrase_pattern: None,
+            submit_phrase_pattern: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct CaptureConfig {
+    /// Where audio comes from: the input device, an audio file or a test signal
+    #[serde(default)]
+    pub source: SourceConfig,
+    /// Audio host to open devices on (e.g. "ALSA", "JACK"); the platform default when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Input device name, or a case-insensitive part of it; the host default when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// How multi-channel input is reduced to mono
+    #[serde(default)]
+    pub downmix: DownmixStrategy,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct VadConfig {
+    /// Stop recording automatically after `silence_secs` of trailing silence
+    pub auto_stop: bool,
+    pub silence_secs: f32,
+    /// Frame level (dBFS) at which speech starts
+    pub speech_threshold: f32,
+    /// Frame level (dBFS) below which speech counts as ended
+    pub silence_threshold: f32,
+    /// How long speech is held after the level drops below `silence_threshold`
+    pub hangover_ms: u32,
+    pub frame_ms: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            auto_stop: false,
+            silence_secs: 3.0,
+            speech_threshold: -40.0,
+            silence_threshold: -50.0,
+            hangover_ms: 300,
+            frame_ms: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FillersConfig {
+    /// Filler words and phrases, matched as whole words ignoring case
+    pub words: Vec<String>,
+    /// Collapse a word said twice in a row, as in "the the"; off by default
+    /// since it can change what was meant
+    pub collapse_repeats: bool,
+}
+
+impl Default for FillersConfig {
+    fn default() -> Self {
+        Self {
+            words: ["um", "umm", "uh", "uhm", "erm", "er", "hmm", "you know"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            collapse_repeats: false,
         }
     }
 }
 
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct PostprocessConfig {
+    /// The rewrites applied to every transcription, in order
+    pub stages: Vec<Stage>,
+}
+
+impl Default for PostprocessConfig {
+    fn default() -> Self {
+        Self {
+            stages: vec![Stage::StopPhrase, Stage::Undo, Stage::Replacements],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct PunctuationConfig {
+    /// A word that must precede every command, as in "say comma", so
+    /// ordinary words like "period" stay words
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ReplacementsConfig {
+    /// A TOML file with a `rules` list or a CSV file of `from,to` rows,
+    /// applied after `rules`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    pub rules: Vec<ReplacementRule>,
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct AppConfig {
     pub whisper: WhisperConfig,
     pub behavior: BehaviorConfig,
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub vad: VadConfig,
+    #[serde(default)]
+    pub postprocess: PostprocessConfig,
+    #[serde(default)]
+    pub fillers: FillersConfig,
+    #[serde(default)]
+    pub punctuation: PunctuationConfig,
+    #[serde(default)]
+    pub replacements: ReplacementsConfig,
 }
 
 impl Default for AppConfig {
@@ -56,6 +210,12 @@ impl Default for AppConfig {
         Self {
             whisper: WhisperConfig::default(),
             behavior: BehaviorConfig::default(),
+            capture: CaptureConfig::default(),
+            vad: VadConfig::default(),
+            postprocess: PostprocessConfig::default(),
+            fillers: FillersConfig::default(),
+            punctuation: PunctuationConfig::default(),
+            replacements: ReplacementsConfig::default(),
         }
     }
 }
@@ -77,7 +237,9 @@ impl AppConfig {
         Ok(home_config)
     }
 
-    pub fn new() -> Result<Self> {
+    /// Loads the configuration, with the settings of `profile` (or of the
+    /// file's `profile` key) applied on top.
+    pub fn new(profile: Option<&str>) -> Result<Self> {
         let config_path = Self::get_config_path()?;
 
         // Create default config in home directory if it doesn't exist
@@ -92,11 +254,16 @@ impl AppConfig {
             std::fs::write(&config_path, toml)?;
         }
 
+        Self::load(&config_path, profile)
+    }
+
+    /// Loads the configuration from `config_path`, which need not exist.
+    pub fn load(config_path: &Path, profile: Option<&str>) -> Result<Self> {
         // Build configuration with the following priority (highest to lowest):
         // 1. Environment variables (SPEAK_*)
         // 2. Configuration file (from project dir or home dir)
         // 3. Default values
-        let config = Config::builder()
+        let builder = Config::builder()
             // Start with default values
             .set_default("whisper.model_path", "models/ggml-small.en.bin")?
             .set_default("whisper.use_gpu", true)?
@@ -111,12 +278,89 @@ impl AppConfig {
             // Add configuration file
             .add_source(File::with_name(config_path.to_str().unwrap()).required(false))
             // Add environment variables with prefix SPEAK_
-            .add_source(Environment::with_prefix("SPEAK").separator("_"))
-            .build()?;
+            .add_source(Environment::with_prefix("SPEAK").separator("_"));
+        let config = builder.clone().build()?;
+
+        // A profile is a `[profiles.<name>]` table repeating the settings it changes
+        let profile = profile
+            .map(str::to_string)
+            .or_else(|| config.get_string("profile").ok());
+        let config = match profile {
+            Some(name) => {
+                let table = config
+                    .get_table(&format!("profiles.{}", name))
+                    .with_context(|| format!("Unknown profile \"{}\"", name))?;
+                log::info!("using profile {}", name);
+
+                let mut overrides = Vec::new();
+                profile_overrides("", table, &mut overrides);
+                let mut builder = builder;
+                for (key, value) in overrides {
+                    builder = builder.set_override(key, value)?;
+                }
+                builder.build()?
+            }
+            None => config,
+        };
 
         // Deserialize the configuration
-        let app_config = config.try_deserialize()?;
+        let mut app_config: AppConfig = config.try_deserialize()?;
+
+        // A relative vocabulary file is next to speak.toml, like the replacements file
+        let dir = config_path.parent();
+        if let (Some(Vocabulary::File(path)), Some(dir)) = (&mut app_config.whisper.vocabulary, dir)
+        {
+            *path = dir.join(&*path);
+        }
 
         Ok(app_config)
     }
 }
+
+/// Flattens a profile table into dotted keys, so a profile only replaces the
+/// settings it names instead of whole sections.
+fn profile_overrides(prefix: &str, table: Map<String, Value>, out: &mut Vec<(String, ValueKind)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value.kind {
+            ValueKind::Table(table) => profile_overrides(&key, table, out),
+            kind => out.push((key, kind)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn vocabulary_file_is_next_to_the_config() {
+        let dir = std::env::temp_dir().join(format!("speak-rs-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("test directory");
+        let config_path = dir.join("speak.toml");
+        fs::write(&config_path, "[whisper]\nvocabulary = \"words.txt\"\n").expect("write");
+
+        let config = AppConfig::load(&config_path, None).expect("config");
+        assert_eq!(
+            config.whisper.vocabulary,
+            Some(Vocabulary::File(dir.join("words.txt")))
+        );
+
+        let absolute = PathBuf::from("/etc/speak/words.txt");
+        fs::write(
+            &config_path,
+            format!("[whisper]\nvocabulary = {:?}\n", absolute),
+        )
+        .expect("write");
+        let config = AppConfig::load(&config_path, None).expect("config");
+        assert_eq!(config.whisper.vocabulary, Some(Vocabulary::File(absolute)));
+    }
+}
@@ -0,0 +1,297 @@
+use std::fs::{DirBuilder, File, OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+
+const SOCKET_NAME: &str = "speak-rs.sock";
+const LOCK_NAME: &str = "speak-rs.lock";
+
+/// How long `forward` waits for a starting instance to open its socket.
+const FORWARD_ATTEMPTS: u32 = 20;
+const FORWARD_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// How long a connection may take to send its command or read the reply,
+/// so a client that stalls cannot hold up the socket for everyone else.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Commands accepted on the control socket, one per connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ControlCommand {
+    /// Start recording unless already recording
+    Start,
+    /// Stop recording and transcribe
+    Stop,
+    /// Start or stop recording, like the record button
+    Toggle,
+    /// Stop recording or transcription and discard the result
+    Cancel,
+    /// Print the session state
+    Status,
+    /// Print the current transcription
+    GetText,
+    /// Print the current transcription as whisper produced it, before cleanup
+    GetRawText,
+    /// Bring the window to the front
+    Show,
+    /// Switch the next recordings between transcribing and translating to English
+    ToggleTranslate,
+}
+
+impl ControlCommand {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::Toggle => "toggle",
+            Self::Cancel => "cancel",
+            Self::Status => "status",
+            Self::GetText => "get-text",
+            Self::GetRawText => "get-raw-text",
+            Self::Show => "show",
+            Self::ToggleTranslate => "toggle-translate",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "start" => Some(Self::Start),
+            "stop" => Some(Self::Stop),
+            "toggle" => Some(Self::Toggle),
+            "cancel" => Some(Self::Cancel),
+            "status" => Some(Self::Status),
+            "get-text" => Some(Self::GetText),
+            "get-raw-text" => Some(Self::GetRawText),
+            "show" => Some(Self::Show),
+            "toggle-translate" => Some(Self::ToggleTranslate),
+            _ => None,
+        }
+    }
+}
+
+/// `$XDG_RUNTIME_DIR`, or a directory of the user's own in the temp dir,
+/// which other users can write to.
+fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir().join(format!("speak-rs-{}", uid())),
+    }
+}
+
+fn uid() -> u32 {
+    // SAFETY: getuid has no preconditions and cannot fail
+    unsafe { libc::getuid() }
+}
+
+/// Creates `dir` accessible only to the user, or checks that the existing
+/// one is, so nobody else can take over the lock or the socket.
+fn create_private_dir(dir: &Path) -> Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to create {}", dir.display())),
+    }
+
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?;
+    if !metadata.is_dir() || metadata.uid() != uid() || metadata.mode() & 0o077 != 0 {
+        bail!(
+            "{} must be a directory only the current user can access",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// `$XDG_RUNTIME_DIR/speak-rs.sock`, or `speak-rs-<uid>/speak-rs.sock` in
+/// the temp dir when it is unset.
+pub fn socket_path() -> PathBuf {
+    runtime_dir().join(SOCKET_NAME)
+}
+
+/// The control socket of the single running instance.
+///
+/// Ownership is decided by an exclusive lock on `speak-rs.lock` next to the
+/// socket, held until the process exits, so a socket left behind by a
+/// crashed instance is simply replaced.
+pub struct ControlSocket {
+    listener: UnixListener,
+    lock: File,
+}
+
+impl ControlSocket {
+    /// Claims the instance lock and binds the socket. Returns `None` when
+    /// another instance already holds the lock.
+    pub fn bind() -> Result<Option<Self>> {
+        let dir = runtime_dir();
+        if std::env::var_os("XDG_RUNTIME_DIR").is_none() {
+            create_private_dir(&dir)?;
+        }
+        let lock_path = dir.join(LOCK_NAME);
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", lock_path.display()));
+            }
+        }
+
+        let path = socket_path();
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to bind {}", path.display()))?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+        Ok(Some(Self { listener, lock }))
+    }
+
+    /// Answers commands on a background thread for the rest of the process.
+    ///
+    /// Each connection sends one command line and receives the handler's
+    /// reply, or `error: <message>`, before the socket is closed.
+    pub fn serve<F>(self, handler: F)
+    where
+        F: Fn(ControlCommand) -> Result<String> + Send + 'static,
+    {
+        log::info!("control socket listening on {}", socket_path().display());
+
+        thread::spawn(move || {
+            let _lock = self.lock;
+            for stream in self.listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = handle_connection(stream, &handler) {
+                            log::warn!("control connection failed: {:#}", e);
+                        }
+                    }
+                    Err(e) => log::warn!("failed to accept control connection: {}", e),
+                }
+            }
+        });
+    }
+}
+
+fn handle_connection<F>(stream: UnixStream, handler: &F) -> Result<()>
+where
+    F: Fn(ControlCommand) -> Result<String>,
+{
+    stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let line = line.trim();
+    log::debug!("control command: {}", line);
+
+    let reply = match ControlCommand::parse(line) {
+        Some(command) => handler(command).unwrap_or_else(|e| format!("error: {:#}", e)),
+        None => format!("error: unknown command \"{}\"", line),
+    };
+
+    let mut stream = stream;
+    writeln!(stream, "{}", reply)?;
+    Ok(())
+}
+
+/// Sends `command` to the running instance and returns its reply.
+pub fn send(command: ControlCommand) -> Result<String> {
+    let path = socket_path();
+    let stream = UnixStream::connect(&path)
+        .with_context(|| format!("speak-rs is not running ({} unavailable)", path.display()))?;
+    exchange(stream, command)
+}
+
+/// Like `send`, but gives an instance that holds the lock and is still
+/// starting up a moment to open its socket.
+pub fn forward(command: ControlCommand) -> Result<String> {
+    let mut attempts = 1;
+    loop {
+        match UnixStream::connect(socket_path()) {
+            Ok(stream) => return exchange(stream, command),
+            Err(_) if attempts < FORWARD_ATTEMPTS => {
+                attempts += 1;
+                thread::sleep(FORWARD_RETRY_DELAY);
+            }
+            Err(e) => return Err(e).context("The running instance is not answering"),
+        }
+    }
+}
+
+fn exchange(mut stream: UnixStream, command: ControlCommand) -> Result<String> {
+    writeln!(stream, "{}", command.as_str())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    let reply = reply.trim_end().to_string();
+
+    match reply.strip_prefix("error: ") {
+        Some(message) => bail!("{}", message),
+        None => Ok(reply),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("speak-rs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn creates_a_private_dir() {
+        let dir = temp_dir("private");
+        create_private_dir(&dir).expect("create");
+        let mode = std::fs::metadata(&dir).expect("metadata").mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // Reusing it is fine
+        create_private_dir(&dir).expect("reuse");
+        std::fs::remove_dir(&dir).expect("remove");
+    }
+
+    #[test]
+    fn rejects_a_shared_dir() {
+        let dir = temp_dir("shared");
+        DirBuilder::new().mode(0o755).create(&dir).expect("create");
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).expect("chmod");
+        assert!(create_private_dir(&dir).is_err());
+        std::fs::remove_dir(&dir).expect("remove");
+    }
+
+    #[test]
+    fn answers_a_command() {
+        let (server, mut client) = UnixStream::pair().expect("socket pair");
+        writeln!(client, "status").expect("write");
+        handle_connection(server, &|command| Ok(command.as_str().to_string())).expect("handled");
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).expect("read");
+        assert_eq!(reply, "status\n");
+    }
+
+    #[test]
+    fn gives_up_on_a_silent_client() {
+        let (server, _client) = UnixStream::pair().expect("socket pair");
+        let started = std::time::Instant::now();
+        let result = handle_connection(server, &|_| panic!("no command was sent"));
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < CONNECTION_TIMEOUT * 2);
+    }
+}
@@ -0,0 +1,726 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::{
+    capture,
+    config::{BehaviorConfig, VadConfig},
+    control::{ControlCommand, ControlSocket},
+    postprocess::{self, Pipeline},
+    vad::VoiceActivityDetector,
+    whisper,
+};
+use session::{Effect, Session, SessionEvent, StopReason};
+use utils::{capture_error_message, snapshot_audio, transcription_error_message};
+use worker::{JobId, JobKind, TranscriptionWorker, WorkerEvent};
+
+mod session;
+mod utils;
+mod worker;
+
+pub use session::SessionState;
+
+const TICK: Duration = Duration::from_millis(100);
+const DURATION_INTERVAL: Duration = Duration::from_millis(500);
+const REALTIME_INTERVAL: Duration = Duration::from_millis(3000);
+const VAD_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What a frontend needs to render; the controller never touches a window.
+pub enum ControllerEvent {
+    State(SessionState),
+    /// Seconds recorded so far
+    Duration(f32),
+    /// Progress of the final pass, from 0 to 1
+    Progress(f32),
+    /// The text to display changed
+    Text(String),
+    /// A final transcription is ready, after post-processing
+    Finished {
+        text: String,
+        /// Timestamped segments of the recording
+        segments: Vec<whisper::Segment>,
+        /// The submit phrase ended the recording, so the text should go to
+        /// the output even without `auto_copy`
+        submit: bool,
+    },
+    Error(String),
+    /// The user asked to bring the window to the front
+    Show,
+    /// The task for the next recording
+    Task(whisper::Task),
+    /// The language whisper detected in the latest pass, with `language = "auto"`
+    Language(whisper::DetectedLanguage),
+}
+
+enum Input {
+    Command(ControlCommand, Option<Sender<String>>),
+    Worker(WorkerEvent),
+}
+
+/// Handle for driving a running controller from any thread.
+#[derive(Clone)]
+pub struct ControllerHandle {
+    inputs: Sender<Input>,
+}
+
+impl ControllerHandle {
+    /// Queues a command without waiting for it to run.
+    pub fn post(&self, command: ControlCommand) {
+        if self.inputs.send(Input::Command(command, None)).is_err() {
+            log::error!("Controller is not running");
+        }
+    }
+
+    /// Runs a command and returns its reply: the status, or the text for
+    /// `GetText` and `GetRawText`.
+    pub fn send(&self, command: ControlCommand) -> Result<String> {
+        let (reply, response) = mpsc::channel();
+        self.inputs
+            .send(Input::Command(command, Some(reply)))
+            .map_err(|_| anyhow::anyhow!("controller is not running"))?;
+        Ok(response.recv()?)
+    }
+
+    /// Answers commands arriving on the control socket.
+    pub fn listen(&self, socket: ControlSocket) {
+        let handle = self.clone();
+        socket.serve(move |command| handle.send(command));
+    }
+}
+
+/// How the controller records and what it does with the text.
+pub struct Settings {
+    pub behavior: BehaviorConfig,
+    pub vad: VadConfig,
+    /// The task of the first recording
+    pub task: whisper::Task,
+    /// Rewrites every transcription before it is shown
+    pub pipeline: Pipeline,
+}
+
+/// Fires at most once per `period`; checked on every controller tick.
+struct Interval {
+    period: Duration,
+    next: Instant,
+}
+
+impl Interval {
+    fn new(period: Duration) -> Self {
+        Self {
+            period,
+            next: Instant::now() + period,
+        }
+    }
+
+    fn reset(&mut self, now: Instant) {
+        self.next = now + self.period;
+    }
+
+    fn due(&mut self, now: Instant) -> bool {
+        if now < self.next {
+            return false;
+        }
+        self.reset(now);
+        true
+    }
+}
+
+/// Owns a recording session: capture, transcription jobs, stop phrase and
+/// silence auto-stop. Runs on its own thread and reports through events.
+///
+/// The decisions live in `Session`; the controller feeds it events and
+/// carries out the effects it returns.
+pub struct Controller {
+    recorder: Arc<capture::SimpleAudioCapture>,
+    worker: TranscriptionWorker,
+    behavior: BehaviorConfig,
+    vad: VadConfig,
+    pipeline: Pipeline,
+    on_event: Box<dyn Fn(ControllerEvent) + Send>,
+    session: Session,
+    /// Selected with `ToggleTranslate`; applies from the next recording
+    task: whisper::Task,
+    recording_task: whisper::Task,
+    /// Counts recordings; tags their transcription jobs
+    generation: u64,
+    /// Segments of the last final pass, handed out with `Finished`
+    segments: Vec<whisper::Segment>,
+    detector: Option<VoiceActivityDetector>,
+    duration_interval: Interval,
+    realtime_interval: Interval,
+    vad_interval: Interval,
+}
+
+impl Controller {
+    pub fn spawn<F>(
+        recorder: Arc<capture::SimpleAudioCapture>,
+        transcriber: Box<dyn whisper::Transcriber>,
+        settings: Settings,
+        on_event: F,
+    ) -> ControllerHandle
+    where
+        F: Fn(ControllerEvent) + Send + 'static,
+    {
+        let (inputs, receiver) = mpsc::channel();
+
+        let worker = {
+            let inputs = inputs.clone();
+            TranscriptionWorker::spawn(transcriber, move |event| {
+                let _ = inputs.send(Input::Worker(event));
+            })
+        };
+
+        let controller = Self {
+            recorder,
+            worker,
+            session: Session::new(&settings.behavior),
+            task: settings.task,
+            recording_task: settings.task,
+            generation: 0,
+            segments: Vec::new(),
+            behavior: settings.behavior,
+            vad: settings.vad,
+            pipeline: settings.pipeline,
+            on_event: Box::new(on_event),
+            detector: None,
+            duration_interval: Interval::new(DURATION_INTERVAL),
+            realtime_interval: Interval::new(REALTIME_INTERVAL),
+            vad_interval: Interval::new(VAD_INTERVAL),
+        };
+        thread::spawn(move || controller.run(receiver));
+
+        ControllerHandle { inputs }
+    }
+
+    fn run(mut self, receiver: Receiver<Input>) {
+        self.emit(ControllerEvent::Task(self.task));
+
+        loop {
+            match receiver.recv_timeout(TICK) {
+                Ok(Input::Command(command, reply)) => {
+                    let text = self.handle_command(command);
+                    if let Some(reply) = reply {
+                        let _ = reply.send(text);
+                    }
+                }
+                Ok(Input::Worker(event)) => self.handle_worker_event(event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if self.session.state().is_recording() {
+                self.tick();
+            }
+        }
+
+        log::debug!("controller stopped");
+    }
+
+    fn emit(&self, event: ControllerEvent) {
+        (self.on_event)(event);
+    }
+
+    /// Feeds `event` to the session, runs the resulting effects and reports
+    /// what changed.
+    fn dispatch(&mut self, event: SessionEvent) {
+        let state = self.session.state().clone();
+        let text = self.session.text().to_string();
+
+        let mut events = VecDeque::from([event]);
+        while let Some(event) = events.pop_front() {
+            for effect in self.session.handle(event) {
+                events.extend(self.apply(effect));
+            }
+        }
+
+        if *self.session.state() != state {
+            self.emit(ControllerEvent::State(self.session.state().clone()));
+        }
+        if self.session.text() != text {
+            self.emit(ControllerEvent::Text(self.session.text().to_string()));
+        }
+    }
+
+    /// Runs `effect`. Returns the event it leads to right away, like a
+    /// final pass that cannot start, for `dispatch` to feed in next.
+    fn apply(&mut self, effect: Effect) -> Option<SessionEvent> {
+        match effect {
+            Effect::StartCapture => {
+                self.recording_task = self.task;
+                self.generation += 1;
+                self.recorder.clear();
+                self.worker.reset();
+                self.recorder.start();
+                self.detector = None;
+
+                let now = Instant::now();
+                self.duration_interval.reset(now);
+                self.realtime_interval.reset(now);
+                self.vad_interval.reset(now);
+                self.emit(ControllerEvent::Duration(0.0));
+            }
+            Effect::Finalize => {
+                self.recorder.pause();
+
+                log::debug!("queueing final transcription");
+                match snapshot_audio(&self.recorder) {
+                    Ok(audio) => {
+                        self.emit(ControllerEvent::Progress(0.0));
+                        self.worker
+                            .submit(self.job(JobKind::Final), audio, self.recording_task);
+                    }
+                    Err(err) => {
+                        let message = transcription_error_message(&err);
+                        return Some(SessionEvent::FinalFailed(message));
+                    }
+                }
+            }
+            Effect::StopCapture => self.recorder.pause(),
+            Effect::DiscardCapture => {
+                self.recorder.clear();
+                self.worker.reset();
+            }
+            Effect::Finished { text, submit } => {
+                let segments = std::mem::take(&mut self.segments);
+                self.emit(ControllerEvent::Finished {
+                    text,
+                    segments,
+                    submit,
+                });
+            }
+            Effect::Notice(message) => self.emit(ControllerEvent::Error(message)),
+        }
+        None
+    }
+
+    fn handle_command(&mut self, command: ControlCommand) -> String {
+        let recording = self.session.state().is_recording();
+        match command {
+            ControlCommand::Start => self.dispatch(SessionEvent::Start),
+            ControlCommand::Stop => self.dispatch(SessionEvent::Stop(StopReason::User)),
+            ControlCommand::Toggle if recording => {
+                self.dispatch(SessionEvent::Stop(StopReason::User));
+            }
+            ControlCommand::Toggle => self.dispatch(SessionEvent::Start),
+            ControlCommand::Cancel => self.dispatch(SessionEvent::Cancel),
+            ControlCommand::GetText => return self.session.text().to_string(),
+            ControlCommand::GetRawText => return self.session.raw_text().to_string(),
+            ControlCommand::Show => self.emit(ControllerEvent::Show),
+            ControlCommand::ToggleTranslate => {
+                self.task = match self.task {
+                    whisper::Task::Transcribe => whisper::Task::Translate,
+                    whisper::Task::Translate => whisper::Task::Transcribe,
+                };
+                log::debug!("next recording will {}", self.task.as_str());
+                self.emit(ControllerEvent::Task(self.task));
+                return self.task.as_str().to_string();
+            }
+            ControlCommand::Status => {}
+        }
+
+        self.session.state().name().to_string()
+    }
+
+    fn tick(&mut self) {
+        let now = Instant::now();
+
+        if self.duration_interval.due(now) {
+            if let Some(error) = self.recorder.take_error() {
+                self.dispatch(SessionEvent::CaptureFailed {
+                    message: capture_error_message(&error),
+                    fatal: error.stops_recording(),
+                });
+                if !self.session.state().is_recording() {
+                    return;
+                }
+            }
+
+            if let Some(duration) = self.recorder.get_duration() {
+                self.emit(ControllerEvent::Duration(duration));
+            }
+        }
+
+        if self.behavior.realtime_transcribe && self.realtime_interval.due(now) {
+            log::debug!("queueing realtime transcription");
+            match snapshot_audio(&self.recorder) {
+                Ok(audio) => {
+                    self.worker
+                        .submit(self.job(JobKind::Realtime), audio, self.recording_task);
+                    self.dispatch(SessionEvent::RealtimeQueued);
+                }
+                Err(err) => {
+                    let message = transcription_error_message(&err);
+                    self.dispatch(SessionEvent::RealtimeFailed(message));
+                }
+            }
+        }
+
+        if self.vad.auto_stop && self.vad_interval.due(now) && self.silence_detected() {
+            self.dispatch(SessionEvent::Stop(StopReason::Silence));
+        }
+    }
+
+    fn silence_detected(&mut self) -> bool {
+        if self.detector.is_none() {
+            let (Some(sample_rate), Some(channels)) = (
+                self.recorder.get_sample_rate(),
+                self.recorder.get_channels(),
+            ) else {
+                return false;
+            };
+            self.detector = Some(VoiceActivityDetector::new(
+                self.vad.clone(),
+                sample_rate,
+                channels,
+            ));
+        }
+
+        let Some(detector) = self.detector.as_mut() else {
+            return false;
+        };
+        if let Some(samples) = self.recorder.get_audio_since(detector.processed()) {
+            detector.process(&samples);
+        }
+
+        if detector.should_stop() {
+            log::debug!(
+                "{:.1}s of silence detected, stopping recording",
+                detector.trailing_silence()
+            );
+            return true;
+        }
+        false
+    }
+
+    /// A job of the current recording.
+    fn job(&self, kind: JobKind) -> JobId {
+        JobId {
+            kind,
+            generation: self.generation,
+        }
+    }
+
+    fn handle_worker_event(&mut self, event: WorkerEvent) {
+        // A realtime pass of an earlier recording would pass for the text of
+        // this one. Final passes all go to the session, which counts the
+        // stale ones.
+        let stale = match &event {
+            WorkerEvent::Transcribed(id, _) | WorkerEvent::Failed(id, _) => {
+                id.kind == JobKind::Realtime && id.generation != self.generation
+            }
+            WorkerEvent::Progress(_) => false,
+        };
+        if stale {
+            log::debug!("discarding realtime pass of an earlier recording");
+            return;
+        }
+
+        if let WorkerEvent::Transcribed(
+            _,
+            whisper::TranscribeOutput {
+                language: Some(language),
+                ..
+            },
+        ) = &event
+        {
+            self.emit(ControllerEvent::Language(language.clone()));
+        }
+
+        let event = match event {
+            WorkerEvent::Progress(percent) => {
+                // A cancelled pass keeps reporting until the worker finishes it
+                if self.session.state().is_transcribing() {
+                    self.emit(ControllerEvent::Progress(percent as f32 / 100.0));
+                }
+                return;
+            }
+            WorkerEvent::Transcribed(id, output) => {
+                let raw = output.combined.trim().to_string();
+                match id.kind {
+                    JobKind::Realtime => {
+                        let output = self.rewrite(output, false);
+                        SessionEvent::RealtimeResult {
+                            text: output.combined,
+                            raw,
+                        }
+                    }
+                    JobKind::Final => {
+                        let output = self.rewrite(output, true);
+                        self.segments = output.segments;
+                        SessionEvent::FinalResult {
+                            text: output.combined,
+                            raw,
+                        }
+                    }
+                }
+            }
+            WorkerEvent::Failed(id, err) => {
+                let message = transcription_error_message(&err);
+                match id.kind {
+                    JobKind::Realtime => SessionEvent::RealtimeFailed(message),
+                    JobKind::Final => SessionEvent::FinalFailed(message),
+                }
+            }
+        };
+        self.dispatch(event);
+    }
+
+    /// Runs the post-processing pipeline over a pass, in the language the
+    /// text came out in.
+    fn rewrite(
+        &mut self,
+        mut output: whisper::TranscribeOutput,
+        final_pass: bool,
+    ) -> whisper::TranscribeOutput {
+        let language = match self.recording_task {
+            whisper::Task::Translate => Some("en".to_string()),
+            whisper::Task::Transcribe => output.language.as_ref().map(|l| l.code.clone()),
+        };
+        let context = postprocess::Context {
+            language: language.as_deref(),
+            final_pass,
+        };
+
+        self.pipeline.process(&mut output, &context);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::capture::SourceConfig;
+    use crate::config::{AppConfig, CaptureConfig};
+    use crate::postprocess::Stage;
+    use crate::whisper::{MockTranscriber, Segment};
+
+    const TIMEOUT: Duration = Duration::from_secs(20);
+
+    fn segment(start: usize, end: usize, text: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            confidence: 1.0,
+            words: Vec::new(),
+        }
+    }
+
+    /// Writes a few seconds of a quiet tone to a WAV file of its own.
+    fn speech_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("speak-rs-{}-{}.wav", name, std::process::id()));
+        let samples: Vec<f32> = (0..3 * 16000)
+            .map(|i| ((i as f32) * 0.05).sin() * 0.2)
+            .collect();
+        std::fs::write(&path, capture::encode_wav(&samples, 16000)).expect("write wav");
+        path
+    }
+
+    /// A controller recording `file` at 8x speed and transcribing with
+    /// `script`, or with `transcriber`.
+    fn spawn(
+        file: PathBuf,
+        script: Vec<Vec<Segment>>,
+        behavior: BehaviorConfig,
+    ) -> (ControllerHandle, Receiver<ControllerEvent>) {
+        spawn_with(file, Box::new(MockTranscriber::new(script)), behavior)
+    }
+
+    fn spawn_with(
+        file: PathBuf,
+        transcriber: Box<dyn whisper::Transcriber>,
+        behavior: BehaviorConfig,
+    ) -> (ControllerHandle, Receiver<ControllerEvent>) {
+        let recorder = capture::SimpleAudioCapture::new(CaptureConfig {
+            source: SourceConfig::File {
+                path: file,
+                speed: 8.0,
+            },
+            ..CaptureConfig::default()
+        });
+        let mut config = AppConfig {
+            behavior: behavior.clone(),
+            ..AppConfig::default()
+        };
+        config.postprocess.stages = vec![Stage::StopPhrase, Stage::Undo];
+        let settings = Settings {
+            behavior,
+            vad: VadConfig::default(),
+            task: whisper::Task::Transcribe,
+            pipeline: Pipeline::new(&config, None).expect("pipeline"),
+        };
+
+        let (sender, events) = mpsc::channel();
+        let handle = Controller::spawn(Arc::new(recorder), transcriber, settings, move |event| {
+            let _ = sender.send(event);
+        });
+        (handle, events)
+    }
+
+    /// Answers each pass with `text` once the test releases it.
+    struct Gated {
+        text: &'static str,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl whisper::Transcriber for Gated {
+        fn transcribe_with(
+            &self,
+            _audio: &whisper::InputAudio,
+            _options: whisper::TranscribeOptions,
+        ) -> Result<whisper::TranscribeOutput, crate::error::SpeakError> {
+            let _ = self.release.lock().unwrap().recv();
+            Ok(whisper::TranscribeOutput {
+                combined: self.text.to_string(),
+                segments: vec![segment(0, 100, self.text)],
+                language: None,
+            })
+        }
+    }
+
+    /// Waits for the first event `find` picks.
+    fn wait_for<T>(
+        events: &Receiver<ControllerEvent>,
+        mut find: impl FnMut(ControllerEvent) -> Option<T>,
+    ) -> T {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let event = events.recv_timeout(left).expect("event before the timeout");
+            if let Some(found) = find(event) {
+                return found;
+            }
+        }
+    }
+
+    /// Waits until at least `seconds` of audio are recorded.
+    fn record(events: &Receiver<ControllerEvent>, seconds: f32) {
+        wait_for(events, |event| match event {
+            ControllerEvent::Duration(duration) if duration >= seconds => Some(()),
+            _ => None,
+        });
+    }
+
+    fn finished(events: &Receiver<ControllerEvent>) -> String {
+        wait_for(events, |event| match event {
+            ControllerEvent::Finished { text, .. } => Some(text),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn transcribes_a_file_recording() {
+        let file = speech_file("controller");
+        let script = vec![vec![segment(0, 0, " Hello from the file, that's all.")]];
+        let behavior = BehaviorConfig {
+            realtime_transcribe: false,
+            ..BehaviorConfig::default()
+        };
+        let (handle, events) = spawn(file.clone(), script, behavior);
+
+        assert_eq!(
+            handle.send(ControlCommand::Start).expect("start"),
+            "recording"
+        );
+        record(&events, 2.5);
+        handle.post(ControlCommand::Stop);
+
+        assert_eq!(finished(&events), "Hello from the file,");
+        assert_eq!(handle.send(ControlCommand::Status).expect("status"), "done");
+        assert_eq!(
+            handle.send(ControlCommand::GetRawText).expect("raw text"),
+            "Hello from the file, that's all."
+        );
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn voice_cancel_forgets_the_recording() {
+        let file = speech_file("cancel");
+        let first = vec![segment(0, 100, " Old."), segment(100, 200, " more")];
+        let script = vec![
+            first.clone(),
+            first,
+            vec![segment(0, 100, " More, cancel recording.")],
+            vec![segment(0, 0, " Fresh start.")],
+        ];
+        let behavior = BehaviorConfig {
+            cancel_phrase_pattern: Some(r"(?i)cancel recording\.?".to_string()),
+            ..BehaviorConfig::default()
+        };
+        let (handle, events) = spawn(file.clone(), script, behavior);
+
+        // Two realtime passes commit "Old.", the third one cancels
+        handle.post(ControlCommand::Start);
+        wait_for(&events, |event| match event {
+            ControllerEvent::State(SessionState::Cancelled) => Some(()),
+            _ => None,
+        });
+
+        handle.post(ControlCommand::Start);
+        record(&events, 2.5);
+        handle.post(ControlCommand::Stop);
+        assert_eq!(finished(&events), "Fresh start.");
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn final_pass_that_cannot_start_fails_once() {
+        let missing = std::env::temp_dir().join("speak-rs-missing.wav");
+        let behavior = BehaviorConfig {
+            realtime_transcribe: false,
+            ..BehaviorConfig::default()
+        };
+        let (handle, events) = spawn(missing, Vec::new(), behavior);
+
+        // Stopped before the source reports the missing file, so there is
+        // no audio to snapshot
+        handle.send(ControlCommand::Start).expect("start");
+        assert_eq!(handle.send(ControlCommand::Stop).expect("stop"), "error");
+
+        let errors = events
+            .try_iter()
+            .filter(|event| matches!(event, ControllerEvent::State(SessionState::Error(_))))
+            .count();
+        assert_eq!(errors, 1);
+    }
+
+    #[test]
+    fn drops_realtime_results_of_an_earlier_recording() {
+        let file = speech_file("stale");
+        let (release, gate) = mpsc::channel();
+        let gated = Gated {
+            text: " Old recording.",
+            release: Mutex::new(gate),
+        };
+        let (handle, events) = spawn_with(file.clone(), Box::new(gated), BehaviorConfig::default());
+
+        // The first realtime pass is still running when the recording is
+        // cancelled and the next one starts
+        handle.post(ControlCommand::Start);
+        wait_for(&events, |event| match event {
+            ControllerEvent::State(SessionState::Recording { transcribing: true }) => Some(()),
+            _ => None,
+        });
+        handle.send(ControlCommand::Cancel).expect("cancel");
+        handle.send(ControlCommand::Start).expect("start");
+        release.send(()).expect("release");
+
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(handle.send(ControlCommand::GetText).expect("text"), "");
+        assert!(
+            !events
+                .try_iter()
+                .any(|event| matches!(event, ControllerEvent::Text(text) if !text.is_empty()))
+        );
+        let _ = std::fs::remove_file(file);
+    }
+}
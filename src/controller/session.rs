@@ -0,0 +1,441 @@
+use crate::config::BehaviorConfig;
+
+use super::utils::is_endswith_pattern;
+
+/// Why a recording ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    User,
+    StopPhrase,
+    /// The submit phrase, which also sends the text to the output
+    SubmitPhrase,
+    Silence,
+}
+
+/// Where a session is. `Done`, `Error` and `Cancelled` behave like `Idle`
+/// but remember how the last recording ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionState {
+    Idle,
+    /// Capturing audio; `transcribing` while a realtime pass is in flight
+    Recording {
+        transcribing: bool,
+    },
+    /// Capture stopped and the final pass is running
+    Finalizing,
+    Done,
+    Error(String),
+    Cancelled,
+}
+
+impl SessionState {
+    pub fn is_recording(&self) -> bool {
+        matches!(self, Self::Recording { .. })
+    }
+
+    pub fn is_transcribing(&self) -> bool {
+        matches!(
+            self,
+            Self::Recording { transcribing: true } | Self::Finalizing
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Recording { .. } => "recording",
+            Self::Finalizing => "transcribing",
+            Self::Done => "done",
+            Self::Error(_) => "error",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Inputs that drive the session.
+#[derive(Debug)]
+pub enum SessionEvent {
+    Start,
+    Stop(StopReason),
+    Cancel,
+    RealtimeQueued,
+    /// The display text of a pass, and the text as whisper produced it
+    RealtimeResult {
+        text: String,
+        raw: String,
+    },
+    RealtimeFailed(String),
+    FinalResult {
+        text: String,
+        raw: String,
+    },
+    FinalFailed(String),
+    CaptureFailed {
+        message: String,
+        fatal: bool,
+    },
+}
+
+/// Side effects the controller carries out after a transition.
+#[derive(Debug, PartialEq)]
+pub enum Effect {
+    StartCapture,
+    /// Stop capturing and queue the final pass over the recording
+    Finalize,
+    /// Stop capturing and keep the audio without transcribing it
+    StopCapture,
+    /// Stop capturing and throw the audio away
+    DiscardCapture,
+    /// The final text of a recording; `submit` when the submit phrase
+    /// asked for it to be sent to the output
+    Finished {
+        text: String,
+        submit: bool,
+    },
+    /// Something worth showing that does not change the state
+    Notice(String),
+}
+
+/// The recording session as a plain state machine, free of audio and UI.
+pub struct Session {
+    state: SessionState,
+    text: String,
+    /// Whisper's text before cleanup and rewrites
+    raw: String,
+    stop_phrase: Option<String>,
+    cancel_phrase: Option<String>,
+    submit_phrase: Option<String>,
+    /// The current recording was ended by the submit phrase
+    submit: bool,
+    stale_finals: usize,
+}
+
+impl Session {
+    pub fn new(behavior: &BehaviorConfig) -> Self {
+        Self {
+            state: SessionState::Idle,
+            text: String::new(),
+            raw: String::new(),
+            stop_phrase: behavior
+                .stop_phrase_enabled
+                .then(|| behavior.stop_phrase_pattern.clone()),
+            cancel_phrase: behavior.cancel_phrase_pattern.clone(),
+            submit_phrase: behavior.submit_phrase_pattern.clone(),
+            submit: false,
+            stale_finals: 0,
+        }
+    }
+
+    pub fn state(&self) -> &SessionState {
+        &self.state
+    }
+
+    /// The transcription to display: realtime text while recording, the
+    /// final text afterwards.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The transcription as whisper produced it, before filler removal,
+    /// spoken punctuation and replacements.
+    pub fn raw_text(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn handle(&mut self, event: SessionEvent) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        self.transition(event, &mut effects);
+        effects
+    }
+
+    fn transition(&mut self, event: SessionEvent, effects: &mut Vec<Effect>) {
+        use SessionState as S;
+
+        match (&self.state, event) {
+            (S::Recording { .. }, SessionEvent::Start) => {}
+            (S::Finalizing, SessionEvent::Start) => {
+                log::debug!("final transcription in progress, ignoring record request");
+            }
+            (_, SessionEvent::Start) => {
+                self.state = S::Recording {
+                    transcribing: false,
+                };
+                self.text.clear();
+                self.raw.clear();
+                effects.push(Effect::StartCapture);
+            }
+            (S::Recording { .. }, SessionEvent::Stop(reason)) => {
+                log::debug!("stopping recording: {:?}", reason);
+                self.submit = reason == StopReason::SubmitPhrase;
+                self.state = S::Finalizing;
+                effects.push(Effect::Finalize);
+            }
+            (S::Recording { .. }, SessionEvent::Cancel) => {
+                log::debug!("recording cancelled");
+                self.state = S::Cancelled;
+                self.text.clear();
+                self.raw.clear();
+                effects.push(Effect::DiscardCapture);
+            }
+            (S::Finalizing, SessionEvent::Cancel) => {
+                // The worker still delivers this pass; drop it when it arrives
+                log::debug!("final transcription cancelled");
+                self.stale_finals += 1;
+                self.state = S::Cancelled;
+                self.text.clear();
+                self.raw.clear();
+            }
+            (S::Recording { .. }, SessionEvent::RealtimeQueued) => {
+                self.state = S::Recording { transcribing: true };
+            }
+            (S::Recording { .. }, SessionEvent::RealtimeResult { text, raw }) => {
+                self.state = S::Recording {
+                    transcribing: false,
+                };
+                if raw.is_empty() {
+                    return;
+                }
+
+                // Phrases are matched on whisper's own text, which
+                // post-processing has not touched
+                if ends_with(&self.cancel_phrase, &raw) {
+                    log::debug!("cancel phrase detected");
+                    self.transition(SessionEvent::Cancel, effects);
+                    return;
+                }
+                if ends_with(&self.submit_phrase, &raw) {
+                    log::debug!("submit phrase detected");
+                    self.transition(SessionEvent::Stop(StopReason::SubmitPhrase), effects);
+                    return;
+                }
+                if ends_with(&self.stop_phrase, &raw) {
+                    log::debug!("stopping phrase detected");
+                    self.transition(SessionEvent::Stop(StopReason::StopPhrase), effects);
+                    return;
+                }
+                self.text = text;
+                self.raw = raw;
+            }
+            (S::Recording { .. }, SessionEvent::RealtimeFailed(message)) => {
+                self.state = S::Recording {
+                    transcribing: false,
+                };
+                effects.push(Effect::Notice(message));
+            }
+            (_, SessionEvent::FinalResult { .. } | SessionEvent::FinalFailed(_))
+                if self.stale_finals > 0 =>
+            {
+                self.stale_finals -= 1;
+                log::debug!("discarding cancelled transcription");
+            }
+            (S::Finalizing, SessionEvent::FinalResult { text, raw }) => {
+                // Without realtime passes the phrases are only heard here
+                if ends_with(&self.cancel_phrase, &raw) {
+                    log::debug!("cancel phrase detected, discarding transcription");
+                    self.state = S::Cancelled;
+                    self.text.clear();
+                    self.raw.clear();
+                    return;
+                }
+                self.submit |= ends_with(&self.submit_phrase, &raw);
+
+                self.state = S::Done;
+                self.raw = raw;
+                if text.is_empty() {
+                    return;
+                }
+
+                self.text = text.clone();
+                effects.push(Effect::Finished {
+                    text,
+                    submit: self.submit,
+                });
+            }
+            (S::Finalizing, SessionEvent::FinalFailed(message)) => {
+                self.state = S::Error(message);
+            }
+            (
+                S::Recording { .. },
+                SessionEvent::CaptureFailed {
+                    message,
+                    fatal: true,
+                },
+            ) => {
+                self.state = S::Error(message);
+                effects.push(Effect::StopCapture);
+            }
+            (_, SessionEvent::CaptureFailed { message, .. }) => {
+                effects.push(Effect::Notice(message));
+            }
+            (state, event) => log::debug!("ignoring {:?} while {}", event, state.name()),
+        }
+    }
+}
+
+/// True when `pattern` is set and `text` ends with it.
+fn ends_with(pattern: &Option<String>, text: &str) -> bool {
+    pattern
+        .as_deref()
+        .is_some_and(|pattern| is_endswith_pattern(text, pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session::new(&BehaviorConfig {
+            cancel_phrase_pattern: Some(r"(?i)cancel recording\.?".to_string()),
+            submit_phrase_pattern: Some(r"(?i)send it\.?".to_string()),
+            ..BehaviorConfig::default()
+        })
+    }
+
+    fn realtime(text: &str) -> SessionEvent {
+        SessionEvent::RealtimeResult {
+            text: text.to_string(),
+            raw: text.to_string(),
+        }
+    }
+
+    fn final_result(text: &str) -> SessionEvent {
+        SessionEvent::FinalResult {
+            text: text.to_string(),
+            raw: text.to_string(),
+        }
+    }
+
+    fn finished(text: &str, submit: bool) -> Effect {
+        Effect::Finished {
+            text: text.to_string(),
+            submit,
+        }
+    }
+
+    fn recording(session: &mut Session) {
+        assert_eq!(session.handle(SessionEvent::Start), [Effect::StartCapture]);
+        assert!(session.state().is_recording());
+    }
+
+    #[test]
+    fn start_stop_final_result() {
+        let mut session = session();
+        recording(&mut session);
+        assert!(session.handle(realtime("Hello")).is_empty());
+        assert_eq!(session.text(), "Hello");
+
+        let effects = session.handle(SessionEvent::Stop(StopReason::User));
+        assert_eq!(effects, [Effect::Finalize]);
+        assert_eq!(*session.state(), SessionState::Finalizing);
+
+        let effects = session.handle(final_result("Hello world."));
+        assert_eq!(effects, [finished("Hello world.", false)]);
+        assert_eq!(*session.state(), SessionState::Done);
+        assert_eq!(session.text(), "Hello world.");
+    }
+
+    #[test]
+    fn silence_stops_recording() {
+        let mut session = session();
+        recording(&mut session);
+        let effects = session.handle(SessionEvent::Stop(StopReason::Silence));
+        assert_eq!(effects, [Effect::Finalize]);
+        assert_eq!(*session.state(), SessionState::Finalizing);
+    }
+
+    #[test]
+    fn cancel_while_recording() {
+        let mut session = session();
+        recording(&mut session);
+        session.handle(realtime("Hello"));
+
+        assert_eq!(
+            session.handle(SessionEvent::Cancel),
+            [Effect::DiscardCapture]
+        );
+        assert_eq!(*session.state(), SessionState::Cancelled);
+        assert_eq!(session.text(), "");
+    }
+
+    #[test]
+    fn cancel_while_finalizing_discards_the_stale_final() {
+        let mut session = session();
+        recording(&mut session);
+        session.handle(SessionEvent::Stop(StopReason::User));
+        assert!(session.handle(SessionEvent::Cancel).is_empty());
+        assert_eq!(*session.state(), SessionState::Cancelled);
+
+        // The next recording gets its own result, not the cancelled one
+        recording(&mut session);
+        session.handle(SessionEvent::Stop(StopReason::User));
+        assert!(session.handle(final_result("Old text.")).is_empty());
+        assert_eq!(*session.state(), SessionState::Finalizing);
+
+        let effects = session.handle(final_result("New text."));
+        assert_eq!(effects, [finished("New text.", false)]);
+        assert_eq!(*session.state(), SessionState::Done);
+    }
+
+    #[test]
+    fn stop_phrase_stops_recording() {
+        let mut session = session();
+        recording(&mut session);
+        let effects = session.handle(realtime("Note this, that's all."));
+        assert_eq!(effects, [Effect::Finalize]);
+        assert_eq!(*session.state(), SessionState::Finalizing);
+
+        let effects = session.handle(final_result("Note this."));
+        assert_eq!(effects, [finished("Note this.", false)]);
+    }
+
+    #[test]
+    fn cancel_phrase_discards_recording() {
+        let mut session = session();
+        recording(&mut session);
+        let effects = session.handle(realtime("Never mind, cancel recording."));
+        assert_eq!(effects, [Effect::DiscardCapture]);
+        assert_eq!(*session.state(), SessionState::Cancelled);
+        assert_eq!(session.text(), "");
+
+        // Heard only in the final pass
+        recording(&mut session);
+        session.handle(SessionEvent::Stop(StopReason::User));
+        assert!(
+            session
+                .handle(final_result("Oops, cancel recording"))
+                .is_empty()
+        );
+        assert_eq!(*session.state(), SessionState::Cancelled);
+    }
+
+    #[test]
+    fn submit_phrase_stops_and_submits() {
+        let mut session = session();
+        recording(&mut session);
+        let effects = session.handle(realtime("Looks good, send it."));
+        assert_eq!(effects, [Effect::Finalize]);
+
+        let effects = session.handle(final_result("Looks good."));
+        assert_eq!(effects, [finished("Looks good.", true)]);
+
+        // Submitting does not carry over to the next recording
+        recording(&mut session);
+        session.handle(SessionEvent::Stop(StopReason::User));
+        let effects = session.handle(final_result("Looks good."));
+        assert_eq!(effects, [finished("Looks good.", false)]);
+    }
+
+    #[test]
+    fn phrases_only_count_at_the_end() {
+        let mut session = session();
+        recording(&mut session);
+        for text in [
+            "Send it to Bob",
+            "Cancel recording was said",
+            "That's all there is",
+        ] {
+            assert!(session.handle(realtime(text)).is_empty());
+            assert!(session.state().is_recording());
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use log;
+use regex::Regex;
+
+use crate::{capture, error::SpeakError, whisper};
+
+pub fn transcription_error_message(error: &anyhow::Error) -> String {
+    log::error!("Transcription error: {:#}", error);
+    match error.downcast_ref::<SpeakError>() {
+        Some(error) => format!("Error: {}", error_message(error)),
+        None => format!("Error: {}", error),
+    }
+}
+
+pub fn capture_error_message(error: &SpeakError) -> String {
+    let prefix = match error {
+        SpeakError::DeviceNotFound { .. } => "Warning",
+        _ => "Error",
+    };
+    format!("{}: {}", prefix, error_message(error))
+}
+
+/// Describes the error together with what the user can do about it.
+pub fn error_message(error: &SpeakError) -> String {
+    format!("{}.\n{}.", error, error.recovery())
+}
+
+pub fn snapshot_audio(recorder: &capture::SimpleAudioCapture) -> Result<capture::AudioBuffer> {
+    let data = recorder
+        .get_audio_data()
+        .context("Failed to get audio data")?;
+    let sample_rate = recorder
+        .get_sample_rate()
+        .context("Failed to get sample rate")?;
+    let channels = recorder
+        .get_channels()
+        .context("Failed to get channel count")?;
+
+    Ok(capture::AudioBuffer {
+        data,
+        sample_rate,
+        channels,
+    })
+}
+
+pub fn transcribe_audio<F>(
+    transcriber: &dyn whisper::Transcriber,
+    stream: &mut whisper::StreamState,
+    audio: &capture::AudioBuffer,
+    task: whisper::Task,
+    finalize: bool,
+    on_progress: F,
+) -> Result<whisper::TranscribeOutput>
+where
+    F: FnMut(i32) + 'static,
+{
+    let audio_duration = audio.duration();
+
+    log::debug!(
+        "audio summary:\n- duration: {}\n- sample rate: {}\n- channels: {}",
+        audio_duration,
+        audio.sample_rate,
+        audio.channels
+    );
+
+    if audio_duration < 2.0 {
+        log::warn!("audio duration is less than 2 seconds");
+        return Ok(whisper::TranscribeOutput::default());
+    }
+
+    let transcription = stream
+        .transcribe(
+            transcriber,
+            &whisper::InputAudio {
+                data: &audio.data,
+                sample_rate: audio.sample_rate,
+                channels: audio.channels,
+            },
+            task,
+            finalize,
+            on_progress,
+        )
+        .context("Failed to transcribe audio")?;
+
+    log::debug!("transcription: {}", transcription.combined);
+    Ok(transcription)
+}
+
+pub fn is_endswith_pattern(text: &str, pattern: &str) -> bool {
+    if let Ok(re) = Regex::new(&format!("{}$", pattern)) {
+        if re.is_match(text) {
+            return true;
+        }
+    }
+    false
+}
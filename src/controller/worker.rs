@@ -0,0 +1,349 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use super::utils::transcribe_audio;
+use crate::{capture, whisper};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobKind {
+    Realtime,
+    Final,
+}
+
+/// Which job an event belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JobId {
+    pub kind: JobKind,
+    /// The recording the audio is from, so results of an earlier one can be
+    /// told apart
+    pub generation: u64,
+}
+
+pub enum WorkerEvent {
+    /// Percent done of the running final pass
+    Progress(i32),
+    Transcribed(JobId, whisper::TranscribeOutput),
+    Failed(JobId, anyhow::Error),
+}
+
+struct Job {
+    id: JobId,
+    audio: capture::AudioBuffer,
+    task: whisper::Task,
+}
+
+enum Request {
+    Transcribe(Job),
+    /// Forget the stream of the previous recording
+    Reset,
+}
+
+/// Runs the transcriber on a dedicated thread so the UI event loop never blocks on inference.
+pub struct TranscriptionWorker {
+    jobs: Sender<Request>,
+    realtime_pending: Arc<AtomicBool>,
+}
+
+impl TranscriptionWorker {
+    pub fn spawn<F>(transcriber: Box<dyn whisper::Transcriber>, on_event: F) -> Self
+    where
+        F: Fn(WorkerEvent) + Send + 'static,
+    {
+        let (jobs, receiver) = mpsc::channel();
+        let realtime_pending = Arc::new(AtomicBool::new(false));
+
+        {
+            let realtime_pending = realtime_pending.clone();
+            thread::spawn(move || {
+                run(transcriber, receiver, realtime_pending, Rc::new(on_event));
+            });
+        }
+
+        Self {
+            jobs,
+            realtime_pending,
+        }
+    }
+
+    /// Queues a transcription job. Realtime jobs are dropped while another
+    /// realtime job is still waiting, so a slow model never builds a backlog.
+    pub fn submit(&self, id: JobId, audio: capture::AudioBuffer, task: whisper::Task) {
+        if id.kind == JobKind::Realtime && self.realtime_pending.swap(true, Ordering::SeqCst) {
+            log::debug!("realtime job already queued, skipping");
+            return;
+        }
+
+        self.send(Request::Transcribe(Job { id, audio, task }));
+    }
+
+    /// Starts the next pass from an empty stream. Needed whenever a
+    /// recording ends without a final pass, which would otherwise reset it.
+    pub fn reset(&self) {
+        // A realtime job still waiting is skipped for the reset
+        self.realtime_pending.store(false, Ordering::SeqCst);
+        self.send(Request::Reset);
+    }
+
+    fn send(&self, request: Request) {
+        if self.jobs.send(request).is_err() {
+            log::error!("Transcription worker is not running");
+        }
+    }
+}
+
+fn run(
+    transcriber: Box<dyn whisper::Transcriber>,
+    receiver: Receiver<Request>,
+    realtime_pending: Arc<AtomicBool>,
+    on_event: Rc<dyn Fn(WorkerEvent)>,
+) {
+    let mut stream = whisper::StreamState::new();
+    let mut queue = VecDeque::new();
+
+    loop {
+        if queue.is_empty() {
+            match receiver.recv() {
+                Ok(request) => queue.push_back(request),
+                Err(_) => break,
+            }
+        }
+        queue.extend(receiver.try_iter());
+        let request = next_request(&mut queue);
+        // Only a realtime job at the back of the queue still runs. Cleared as
+        // well when it was skipped, or `submit` would drop every realtime job
+        // after it
+        let waiting = matches!(
+            queue.back(),
+            Some(Request::Transcribe(job)) if job.id.kind == JobKind::Realtime
+        );
+        realtime_pending.store(waiting, Ordering::SeqCst);
+        let job = match request {
+            Some(Request::Transcribe(job)) => job,
+            Some(Request::Reset) => {
+                log::debug!("transcription stream reset");
+                stream.reset();
+                continue;
+            }
+            None => continue,
+        };
+
+        let id = job.id;
+        log::debug!("transcription worker running {:?} job", id.kind);
+
+        // Only the final pass has a progress bar; realtime passes are short
+        let finalize = id.kind == JobKind::Final;
+        let progress = {
+            let on_event = on_event.clone();
+            move |percent| {
+                if finalize {
+                    on_event(WorkerEvent::Progress(percent));
+                }
+            }
+        };
+
+        match transcribe_audio(
+            transcriber.as_ref(),
+            &mut stream,
+            &job.audio,
+            job.task,
+            finalize,
+            progress,
+        ) {
+            Ok(output) => on_event(WorkerEvent::Transcribed(id, output)),
+            Err(err) => on_event(WorkerEvent::Failed(id, err)),
+        }
+
+        // Start the next recording from a clean stream even if this pass failed
+        if finalize {
+            stream.reset();
+        }
+    }
+
+    log::debug!("transcription worker stopped");
+}
+
+/// Takes the next request to run. A realtime pass is skipped once anything
+/// is queued behind it, since a newer pass or a reset makes it stale. Final
+/// passes always run: the session expects a result for each of them.
+fn next_request(queue: &mut VecDeque<Request>) -> Option<Request> {
+    while let Some(request) = queue.pop_front() {
+        if matches!(&request, Request::Transcribe(job) if job.id.kind == JobKind::Realtime)
+            && !queue.is_empty()
+        {
+            log::debug!("skipping superseded realtime job");
+            continue;
+        }
+        return Some(request);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::whisper::{MockTranscriber, Segment};
+
+    /// Holds each pass until the test releases it.
+    struct Gated {
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl whisper::Transcriber for Gated {
+        fn transcribe_with(
+            &self,
+            _audio: &whisper::InputAudio,
+            _options: whisper::TranscribeOptions,
+        ) -> Result<whisper::TranscribeOutput, crate::error::SpeakError> {
+            let _ = self.release.lock().unwrap().recv();
+            Ok(whisper::TranscribeOutput::default())
+        }
+    }
+
+    fn audio(seconds: usize) -> capture::AudioBuffer {
+        capture::AudioBuffer {
+            data: vec![0.0; seconds * 16000],
+            sample_rate: 16000,
+            channels: 1,
+        }
+    }
+
+    fn id(kind: JobKind) -> JobId {
+        JobId {
+            kind,
+            generation: 0,
+        }
+    }
+
+    fn job(kind: JobKind) -> Request {
+        Request::Transcribe(Job {
+            id: id(kind),
+            audio: audio(0),
+            task: whisper::Task::Transcribe,
+        })
+    }
+
+    /// The kinds of the jobs that run, with None for a reset.
+    fn kinds(queue: &mut VecDeque<Request>) -> Vec<Option<JobKind>> {
+        std::iter::from_fn(|| {
+            next_request(queue).map(|request| match request {
+                Request::Transcribe(job) => Some(job.id.kind),
+                Request::Reset => None,
+            })
+        })
+        .collect()
+    }
+
+    fn segment(start: usize, end: usize, text: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            confidence: 1.0,
+            words: Vec::new(),
+        }
+    }
+
+    /// Waits for the result of the next pass.
+    fn transcribed(events: &Receiver<WorkerEvent>) -> whisper::TranscribeOutput {
+        loop {
+            match events.recv_timeout(Duration::from_secs(5)) {
+                Ok(WorkerEvent::Transcribed(_, output)) => return output,
+                Ok(WorkerEvent::Failed(_, err)) => panic!("pass failed: {:#}", err),
+                Ok(WorkerEvent::Progress(_)) => {}
+                Err(err) => panic!("no result: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn realtime_jobs_are_superseded() {
+        let mut queue = VecDeque::from([job(JobKind::Realtime), job(JobKind::Realtime)]);
+        assert_eq!(kinds(&mut queue), [Some(JobKind::Realtime)]);
+
+        let mut queue = VecDeque::from([job(JobKind::Realtime), job(JobKind::Final)]);
+        assert_eq!(kinds(&mut queue), [Some(JobKind::Final)]);
+
+        let mut queue = VecDeque::from([job(JobKind::Realtime), Request::Reset]);
+        assert_eq!(kinds(&mut queue), [None]);
+    }
+
+    #[test]
+    fn final_jobs_are_never_dropped() {
+        let mut queue = VecDeque::from([
+            job(JobKind::Final),
+            job(JobKind::Realtime),
+            job(JobKind::Final),
+        ]);
+        assert_eq!(
+            kinds(&mut queue),
+            [Some(JobKind::Final), Some(JobKind::Final)]
+        );
+
+        let mut queue = VecDeque::from([job(JobKind::Final), job(JobKind::Realtime)]);
+        assert_eq!(
+            kinds(&mut queue),
+            [Some(JobKind::Final), Some(JobKind::Realtime)]
+        );
+    }
+
+    #[test]
+    fn reset_forgets_the_previous_recording() {
+        let pass = vec![segment(0, 100, " One."), segment(100, 200, " Two.")];
+        let mock = MockTranscriber::new(vec![pass.clone(), pass, vec![segment(0, 100, " Fresh.")]]);
+        let (sender, events) = mpsc::channel();
+        let worker = TranscriptionWorker::spawn(Box::new(mock), move |event| {
+            let _ = sender.send(event);
+        });
+        let task = whisper::Task::Transcribe;
+
+        // Two identical passes commit the first second of a recording
+        worker.submit(id(JobKind::Realtime), audio(3), task);
+        transcribed(&events);
+        worker.submit(id(JobKind::Realtime), audio(3), task);
+        assert_eq!(transcribed(&events).combined, " One. Two.");
+
+        // It was cancelled; the next, longer recording starts from scratch
+        worker.reset();
+        worker.submit(id(JobKind::Realtime), audio(5), task);
+        assert_eq!(transcribed(&events).combined, " Fresh.");
+    }
+
+    #[test]
+    fn realtime_jobs_run_after_a_skipped_one() {
+        let (release, gate) = mpsc::channel();
+        let gated = Gated {
+            release: Mutex::new(gate),
+        };
+        let (sender, events) = mpsc::channel();
+        let worker = TranscriptionWorker::spawn(Box::new(gated), move |event| {
+            let _ = sender.send(event);
+        });
+        let task = whisper::Task::Transcribe;
+        let next_kind = || loop {
+            match events.recv_timeout(Duration::from_secs(5)) {
+                Ok(WorkerEvent::Transcribed(id, _)) => return id.kind,
+                Ok(WorkerEvent::Failed(_, err)) => panic!("pass failed: {:#}", err),
+                Ok(WorkerEvent::Progress(_)) => {}
+                Err(err) => panic!("no result: {}", err),
+            }
+        };
+
+        // The realtime job waits behind a busy pass and is skipped for the reset
+        worker.submit(id(JobKind::Final), audio(3), task);
+        worker.submit(id(JobKind::Realtime), audio(3), task);
+        worker.reset();
+        release.send(()).unwrap();
+        assert_eq!(next_kind(), JobKind::Final);
+
+        worker.submit(id(JobKind::Realtime), audio(3), task);
+        release.send(()).unwrap();
+        assert_eq!(next_kind(), JobKind::Realtime);
+    }
+}
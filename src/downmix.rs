@@ -0,0 +1,193 @@
+/// Frames per block when picking the loudest channel.
+const LOUDEST_BLOCK_FRAMES: usize = 512;
+
+/// How interleaved multi-channel audio is reduced to mono.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownmixStrategy {
+    /// Average all channels
+    #[default]
+    Average,
+    /// Use a single channel (0-based); out of range picks the last channel
+    Channel(usize),
+    /// Use whichever channel has the most energy in each block
+    Loudest,
+}
+
+/// Incremental downmixer that accepts input split anywhere, even mid-frame.
+pub struct Downmixer {
+    channels: usize,
+    strategy: DownmixStrategy,
+    buffer: Vec<f32>,
+}
+
+impl Downmixer {
+    pub fn new(channels: usize, strategy: DownmixStrategy) -> Self {
+        Self {
+            channels: channels.max(1),
+            strategy,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Appends the mono samples for every complete frame (or block) to `out`.
+    pub fn process(&mut self, data: &[f32], out: &mut Vec<f32>) {
+        if self.channels == 1 {
+            out.extend_from_slice(data);
+            return;
+        }
+
+        self.buffer.extend_from_slice(data);
+        let unit = match self.strategy {
+            DownmixStrategy::Loudest => LOUDEST_BLOCK_FRAMES * self.channels,
+            _ => self.channels,
+        };
+        let ready = self.buffer.len() - self.buffer.len() % unit;
+        if ready == 0 {
+            return;
+        }
+
+        let block_len = match self.strategy {
+            DownmixStrategy::Loudest => unit,
+            _ => ready,
+        };
+        for block in self.buffer[..ready].chunks(block_len) {
+            mix_block(block, self.channels, self.strategy, out);
+        }
+        self.buffer.drain(..ready);
+    }
+
+    /// Mixes any complete frames still held back; a trailing partial frame is dropped.
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        let ready = self.buffer.len() - self.buffer.len() % self.channels;
+        mix_block(&self.buffer[..ready], self.channels, self.strategy, out);
+        self.buffer.clear();
+    }
+}
+
+/// Downmixes a whole interleaved buffer.
+pub fn downmix(data: &[f32], channels: usize, strategy: DownmixStrategy) -> Vec<f32> {
+    let mut out = Vec::with_capacity(data.len() / channels.max(1));
+    let mut downmixer = Downmixer::new(channels, strategy);
+    downmixer.process(data, &mut out);
+    downmixer.flush(&mut out);
+    out
+}
+
+fn mix_block(block: &[f32], channels: usize, strategy: DownmixStrategy, out: &mut Vec<f32>) {
+    let frames = block.chunks_exact(channels);
+    match strategy {
+        DownmixStrategy::Average => {
+            out.extend(frames.map(|f| f.iter().sum::<f32>() / channels as f32));
+        }
+        DownmixStrategy::Channel(channel) => {
+            let channel = channel.min(channels - 1);
+            out.extend(frames.map(|f| f[channel]));
+        }
+        DownmixStrategy::Loudest => {
+            let loudest = (0..channels)
+                .map(|ch| {
+                    let energy: f32 = block.iter().skip(ch).step_by(channels).map(|s| s * s).sum();
+                    (ch, energy)
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map_or(0, |(ch, _)| ch);
+            out.extend(frames.map(|f| f[loudest]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interleaved frames where channel `c` of frame `f` is `f * 10 + c`.
+    fn frames(channels: usize, count: usize) -> Vec<f32> {
+        (0..count)
+            .flat_map(|f| (0..channels).map(move |c| (f * 10 + c) as f32))
+            .collect()
+    }
+
+    #[test]
+    fn average() {
+        assert_eq!(
+            downmix(&frames(1, 3), 1, DownmixStrategy::Average),
+            [0.0, 10.0, 20.0]
+        );
+        assert_eq!(
+            downmix(&frames(2, 3), 2, DownmixStrategy::Average),
+            [0.5, 10.5, 20.5]
+        );
+        assert_eq!(
+            downmix(&frames(4, 3), 4, DownmixStrategy::Average),
+            [1.5, 11.5, 21.5]
+        );
+    }
+
+    #[test]
+    fn channel() {
+        let strategy = DownmixStrategy::Channel(1);
+        assert_eq!(downmix(&frames(1, 2), 1, strategy), [0.0, 10.0]);
+        assert_eq!(downmix(&frames(2, 2), 2, strategy), [1.0, 11.0]);
+        assert_eq!(downmix(&frames(4, 2), 4, strategy), [1.0, 11.0]);
+
+        // Out of range picks the last channel
+        let strategy = DownmixStrategy::Channel(9);
+        assert_eq!(downmix(&frames(1, 2), 1, strategy), [0.0, 10.0]);
+        assert_eq!(downmix(&frames(2, 2), 2, strategy), [1.0, 11.0]);
+        assert_eq!(downmix(&frames(4, 2), 4, strategy), [3.0, 13.0]);
+    }
+
+    #[test]
+    fn loudest() {
+        let strategy = DownmixStrategy::Loudest;
+        assert_eq!(downmix(&[0.1, 0.2], 1, strategy), [0.1, 0.2]);
+
+        // Channel 1 is loud in the first block, channel 0 in the second
+        let mut data = Vec::new();
+        for _ in 0..LOUDEST_BLOCK_FRAMES {
+            data.extend([0.1, 0.9]);
+        }
+        for _ in 0..LOUDEST_BLOCK_FRAMES {
+            data.extend([-0.8, 0.2]);
+        }
+        let out = downmix(&data, 2, strategy);
+        assert_eq!(out.len(), 2 * LOUDEST_BLOCK_FRAMES);
+        assert_eq!(out[0], 0.9);
+        assert_eq!(out[LOUDEST_BLOCK_FRAMES], -0.8);
+
+        // The trailing partial block is mixed on flush
+        let data: Vec<f32> = (0..10).flat_map(|_| [0.0, 0.0, 0.5, -0.1]).collect();
+        assert_eq!(downmix(&data, 4, strategy), [0.5; 10]);
+    }
+
+    #[test]
+    fn blocks_split_mid_frame() {
+        for (channels, strategy) in [
+            (2, DownmixStrategy::Average),
+            (4, DownmixStrategy::Channel(2)),
+            (4, DownmixStrategy::Loudest),
+        ] {
+            let data = frames(channels, 700);
+            let expected = downmix(&data, channels, strategy);
+
+            let mut downmixer = Downmixer::new(channels, strategy);
+            let mut out = Vec::new();
+            for block in data.chunks(7) {
+                downmixer.process(block, &mut out);
+            }
+            downmixer.flush(&mut out);
+            assert_eq!(out, expected, "{} channels, {:?}", channels, strategy);
+        }
+    }
+
+    #[test]
+    fn flush_drops_a_partial_frame() {
+        let mut downmixer = Downmixer::new(2, DownmixStrategy::Average);
+        let mut out = Vec::new();
+        downmixer.process(&[1.0, 3.0, 5.0], &mut out);
+        assert_eq!(out, [2.0]);
+        downmixer.flush(&mut out);
+        assert_eq!(out, [2.0]);
+    }
+}
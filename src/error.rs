@@ -0,0 +1,95 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Failures from capture and transcription that the UI reports individually.
+#[derive(Debug)]
+pub enum SpeakError {
+    ModelLoad { path: PathBuf, reason: String },
+    Resample(String),
+    AudioTooShort,
+    Inference(String),
+    DeviceUnavailable(String),
+    DeviceNotFound { requested: String, fallback: String },
+    UnsupportedFormat(String),
+    StreamError(String),
+    SourceFile { path: PathBuf, reason: String },
+    WordFile { path: PathBuf, reason: String },
+    UnknownLanguage(String),
+}
+
+impl SpeakError {
+    /// What the user can do about the error.
+    pub fn recovery(&self) -> &'static str {
+        match self {
+            Self::ModelLoad { .. } => {
+                "Check whisper.model_path in speak.toml and download the model to that path"
+            }
+            Self::Resample(_) => "Try another input device or sample rate",
+            Self::AudioTooShort => "Record at least a couple of seconds of speech",
+            Self::Inference(_) => "Try again; if it keeps failing set whisper.use_gpu = false",
+            Self::DeviceUnavailable(_) => {
+                "Connect a microphone, or run `speak-rs devices` and set capture.device"
+            }
+            Self::DeviceNotFound { .. } => "Run `speak-rs devices` and update capture.device",
+            Self::UnsupportedFormat(_) => "Choose another device with capture.device",
+            Self::StreamError(_) => "Reconnect the microphone and start recording again",
+            Self::SourceFile { .. } => "Check capture.source in speak.toml",
+            Self::WordFile { .. } => "Check whisper.vocabulary in speak.toml",
+            Self::UnknownLanguage(_) => "Use a language code such as \"en\", or \"auto\"",
+        }
+    }
+
+    /// True when recording cannot continue after this error.
+    pub fn stops_recording(&self) -> bool {
+        matches!(
+            self,
+            Self::DeviceUnavailable(_)
+                | Self::UnsupportedFormat(_)
+                | Self::StreamError(_)
+                | Self::Resample(_)
+                | Self::SourceFile { .. }
+        )
+    }
+}
+
+impl fmt::Display for SpeakError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ModelLoad { path, reason } => {
+                write!(f, "Failed to load model {}: {}", path.display(), reason)
+            }
+            Self::Resample(reason) => write!(f, "Failed to resample audio: {}", reason),
+            Self::AudioTooShort => write!(f, "Audio is too short to transcribe"),
+            Self::Inference(reason) => write!(f, "Transcription failed: {}", reason),
+            Self::DeviceUnavailable(reason) => write!(f, "Input device unavailable: {}", reason),
+            Self::DeviceNotFound {
+                requested,
+                fallback,
+            } => write!(f, "{} not found, using \"{}\"", requested, fallback),
+            Self::UnsupportedFormat(format) => {
+                write!(f, "Unsupported input sample format: {}", format)
+            }
+            Self::StreamError(reason) => write!(f, "Audio stream error: {}", reason),
+            Self::SourceFile { path, reason } => {
+                write!(f, "Failed to play {}: {}", path.display(), reason)
+            }
+            Self::WordFile { path, reason } => {
+                write!(
+                    f,
+                    "Failed to read vocabulary {}: {}",
+                    path.display(),
+                    reason
+                )
+            }
+            Self::UnknownLanguage(code) => write!(f, "Unknown language \"{}\"", code),
+        }
+    }
+}
+
+impl std::error::Error for SpeakError {}
+
+impl From<whisper_rs::WhisperError> for SpeakError {
+    fn from(error: whisper_rs::WhisperError) -> Self {
+        Self::Inference(error.to_string())
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::Write;
+
+use serde_json::json;
+
+use crate::whisper::Segment;
+
+/// Output formats for a finished transcription.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// The transcription as plain text
+    #[default]
+    Text,
+    /// One line per segment, prefixed with its time range
+    Timestamped,
+    /// SubRip subtitles
+    Srt,
+    /// WebVTT subtitles
+    Vtt,
+    /// The text and every segment with its timestamps and confidence
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Text | Self::Timestamped => "txt",
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Renders a transcription in `format`. `text` is used as is for plain
+/// text and JSON; the other formats are built from the segments.
+pub fn export(format: ExportFormat, text: &str, segments: &[Segment]) -> String {
+    match format {
+        ExportFormat::Text => format!("{}\n", text.trim()),
+        ExportFormat::Timestamped => to_timestamped(segments),
+        ExportFormat::Srt => to_srt(segments),
+        ExportFormat::Vtt => to_vtt(segments),
+        ExportFormat::Json => to_json(text, segments),
+    }
+}
+
+/// Formats segments as SubRip subtitles.
+pub fn to_srt(segments: &[Segment]) -> String {
+    let mut out = String::new();
+    for (index, segment) in segments.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "{}\n{} --> {}\n{}\n",
+            index + 1,
+            timestamp(segment.start, ','),
+            timestamp(segment.end, ','),
+            segment.text.trim()
+        );
+    }
+    out
+}
+
+/// Formats segments as WebVTT subtitles.
+pub fn to_vtt(segments: &[Segment]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for segment in segments {
+        let _ = writeln!(
+            out,
+            "{} --> {}\n{}\n",
+            timestamp(segment.start, '.'),
+            timestamp(segment.end, '.'),
+            segment.text.trim()
+        );
+    }
+    out
+}
+
+/// Formats segments as text lines like `[00:00:01.500 --> 00:00:04.000] Hello`.
+pub fn to_timestamped(segments: &[Segment]) -> String {
+    let mut out = String::new();
+    for segment in segments {
+        let _ = writeln!(
+            out,
+            "[{} --> {}] {}",
+            timestamp(segment.start, '.'),
+            timestamp(segment.end, '.'),
+            segment.text.trim()
+        );
+    }
+    out
+}
+
+/// Formats the text and its segments, with their words when timed, as
+/// pretty-printed JSON. Times are in seconds.
+pub fn to_json(text: &str, segments: &[Segment]) -> String {
+    let segments: Vec<_> = segments
+        .iter()
+        .map(|segment| {
+            let mut value = json!({
+                "start": segment.start as f64 / 100.0,
+                "end": segment.end as f64 / 100.0,
+                "text": segment.text.trim(),
+                "confidence": segment.confidence,
+            });
+            if !segment.words.is_empty() {
+                let words: Vec<_> = segment
+                    .words
+                    .iter()
+                    .map(|word| {
+                        json!({
+                            "start": word.start as f64 / 100.0,
+                            "end": word.end as f64 / 100.0,
+                            "text": word.text,
+                            "probability": word.probability,
+                        })
+                    })
+                    .collect();
+                value["words"] = words.into();
+            }
+            value
+        })
+        .collect();
+
+    let value = json!({ "text": text.trim(), "segments": segments });
+    let mut out = serde_json::to_string_pretty(&value).unwrap_or_default();
+    out.push('\n');
+    out
+}
+
+/// Formats a centisecond offset as `HH:MM:SS<separator>mmm`.
+fn timestamp(centiseconds: usize, separator: char) -> String {
+    let millis = centiseconds * 10;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::whisper::Word;
+
+    fn segment(start: usize, end: usize, text: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            confidence: 0.5,
+            words: Vec::new(),
+        }
+    }
+
+    fn segments() -> Vec<Segment> {
+        vec![
+            segment(150, 400, " Hello there."),
+            segment(359_950, 360_125, " An hour in."),
+        ]
+    }
+
+    #[test]
+    fn timestamps_roll_over() {
+        assert_eq!(timestamp(0, '.'), "00:00:00.000");
+        assert_eq!(timestamp(6_001, ','), "00:01:00,010");
+        assert_eq!(timestamp(359_999, '.'), "00:59:59.990");
+        assert_eq!(timestamp(360_000, '.'), "01:00:00.000");
+        assert_eq!(timestamp(3_723_456, ','), "10:20:34,560");
+    }
+
+    #[test]
+    fn srt_numbers_cues_with_commas() {
+        assert_eq!(
+            to_srt(&segments()),
+            "1\n00:00:01,500 --> 00:00:04,000\nHello there.\n\n\
+             2\n00:59:59,500 --> 01:00:01,250\nAn hour in.\n\n"
+        );
+    }
+
+    #[test]
+    fn vtt_uses_periods() {
+        assert_eq!(
+            to_vtt(&segments()),
+            "WEBVTT\n\n\
+             00:00:01.500 --> 00:00:04.000\nHello there.\n\n\
+             00:59:59.500 --> 01:00:01.250\nAn hour in.\n\n"
+        );
+    }
+
+    #[test]
+    fn timestamped_lines() {
+        assert_eq!(
+            to_timestamped(&segments()),
+            "[00:00:01.500 --> 00:00:04.000] Hello there.\n\
+             [00:59:59.500 --> 01:00:01.250] An hour in.\n"
+        );
+    }
+
+    #[test]
+    fn json_escapes_the_text() {
+        let mut quoted = segment(0, 250, " She said \"hi\"\\\n");
+        quoted.words = vec![Word {
+            start: 10,
+            end: 60,
+            text: "\"hi\"".to_string(),
+            probability: 0.75,
+        }];
+        let out = to_json(" She said \"hi\"\\\n", &[quoted]);
+        assert!(out.contains(r#""text": "She said \"hi\"\\""#), "{}", out);
+
+        let value: serde_json::Value = serde_json::from_str(&out).expect("valid json");
+        assert_eq!(value["text"], "She said \"hi\"\\");
+        assert_eq!(value["segments"][0]["end"], 2.5);
+        assert_eq!(value["segments"][0]["words"][0]["text"], "\"hi\"");
+        assert_eq!(value["segments"][0]["words"][0]["start"], 0.1);
+    }
+}
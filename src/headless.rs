@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+
+use crate::{
+    capture,
+    control::{ControlCommand, ControlSocket},
+    controller::{Controller, ControllerEvent, SessionState, Settings},
+    whisper,
+};
+
+/// Runs without a window, driven only through the control socket. Each
+/// final transcription is printed to stdout as one line.
+pub fn run(
+    recorder: Arc<capture::SimpleAudioCapture>,
+    transcriber: Box<dyn whisper::Transcriber>,
+    settings: Settings,
+    control: Option<ControlSocket>,
+    start_recording: bool,
+) -> Result<()> {
+    let Some(control) = control else {
+        bail!("Headless mode needs the control socket");
+    };
+
+    let controller = Controller::spawn(recorder, transcriber, settings, print_event);
+    controller.listen(control);
+    if start_recording {
+        controller.post(ControlCommand::Start);
+    }
+
+    log::info!("running headless, use `speak-rs ctl` to record");
+    loop {
+        std::thread::park();
+    }
+}
+
+fn print_event(event: ControllerEvent) {
+    match event {
+        ControllerEvent::State(SessionState::Error(message)) => {
+            log::error!("{}", message.replace('\n', " "));
+        }
+        ControllerEvent::State(state) => log::info!("session {}", state.name()),
+        ControllerEvent::Duration(duration) => log::trace!("recorded {:.1}s", duration),
+        ControllerEvent::Progress(progress) => {
+            log::debug!("transcribing {:.0}%", progress * 100.0);
+        }
+        ControllerEvent::Text(text) => log::debug!("transcription: {}", text),
+        ControllerEvent::Finished {
+            text,
+            segments,
+            submit,
+        } => {
+            log::debug!("finished with {} segments", segments.len());
+            if submit {
+                log::info!("submitted by voice");
+            }
+            println!("{}", text);
+        }
+        ControllerEvent::Error(message) => log::error!("{}", message.replace('\n', " ")),
+        ControllerEvent::Show => {}
+        ControllerEvent::Task(task) => log::info!("recordings will {}", task.as_str()),
+        ControllerEvent::Language(language) => log::debug!(
+            "detected language {} ({:.0}%)",
+            language.code,
+            language.probability * 100.0
+        ),
+    }
+}
@@ -1,14 +1,30 @@
 use std::sync::Arc;
 
 use anyhow::Result;
+use clap::Parser;
 use log::{LevelFilter, info};
 
+use control::ControlCommand;
+
 mod capture;
+mod cli;
 mod config;
+mod control;
+mod controller;
+mod downmix;
+mod error;
+mod export;
+mod headless;
+mod postprocess;
+mod serve;
+#[cfg(feature = "gui")]
 mod ui;
+mod vad;
 mod whisper;
 
 fn main() -> Result<()> {
+    let cli = cli::Cli::parse();
+
     whisper_rs::install_logging_hooks();
     env_logger::Builder::from_default_env()
         .filter_level(LevelFilter::Info)
@@ -16,17 +32,80 @@ fn main() -> Result<()> {
         .format_level(true)
         .init();
 
-    let config = config::AppConfig::new()?;
+    let config = config::AppConfig::new(cli.profile.as_deref())?;
     info!(
         "Configuration loaded from: {}",
         config::AppConfig::get_config_path()?.display()
     );
 
-    let recorder = Arc::new(capture::SimpleAudioCapture::new());
-    let transcriber = Arc::new(whisper::SimpleTranscriber::new(config.whisper)?);
+    if let Some(command) = cli.command {
+        return match command {
+            cli::Command::Transcribe {
+                files,
+                format,
+                output_dir,
+            } => cli::transcribe(
+                config,
+                cli.profile.as_deref(),
+                &files,
+                format,
+                output_dir.as_deref(),
+            ),
+            cli::Command::Devices => cli::devices(),
+            cli::Command::Serve { addr } => serve::serve(config, cli.profile.as_deref(), &addr),
+            cli::Command::Ctl { command } => cli::ctl(command),
+        };
+    }
+
+    // Hand the launch over to a running instance instead of loading a second model
+    let intent = if cli.toggle {
+        ControlCommand::Toggle
+    } else {
+        ControlCommand::Show
+    };
+    let control = match control::ControlSocket::bind() {
+        Ok(Some(socket)) => Some(socket),
+        Ok(None) => {
+            let status = control::forward(intent)?;
+            info!(
+                "speak-rs is already running, sent {} ({})",
+                intent.as_str(),
+                status
+            );
+            return Ok(());
+        }
+        Err(e) => {
+            log::warn!("control socket disabled: {:#}", e);
+            None
+        }
+    };
+
+    let pipeline = postprocess::Pipeline::new(&config, cli.profile.as_deref())?;
+    let recorder = Arc::new(capture::SimpleAudioCapture::new(config.capture));
+    let settings = controller::Settings {
+        task: config.whisper.task,
+        pipeline,
+        behavior: config.behavior,
+        vad: config.vad,
+    };
+    let transcriber =
+        whisper::load(config.whisper).inspect_err(|e| log::error!("{}", e.recovery()))?;
+
+    #[cfg(feature = "gui")]
+    if !cli.headless {
+        let app_ui = ui::AppUI::new(recorder, transcriber, settings)?;
+        let controller = app_ui.controller();
+        if let Some(socket) = control {
+            controller.listen(socket);
+        }
+        if cli.toggle {
+            controller.post(ControlCommand::Start);
+        }
+        app_ui.run()?;
+        return Ok(());
+    }
 
-    let app_ui = ui::AppUI::new(recorder, transcriber, config.behavior)?;
-    app_ui.run()?;
+    headless::run(recorder, transcriber, settings, control, cli.toggle)?;
 
     Ok(())
 }
@@ -0,0 +1,114 @@
+use super::{Context, PostProcessor};
+use crate::whisper::Segment;
+
+/// Capitalizes the first letter of the text, of every sentence and of every
+/// line.
+pub struct Capitalize;
+
+impl Capitalize {
+    /// With `at_start` the text begins a sentence.
+    fn capitalize(text: &str, at_start: bool) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut capitalize = at_start;
+        // Saw ".", "!" or "?"; a sentence only ends if whitespace follows
+        let mut sentence_end = false;
+
+        for c in text.chars() {
+            if capitalize && c.is_alphanumeric() {
+                out.extend(c.to_uppercase());
+                capitalize = false;
+                continue;
+            }
+            out.push(c);
+
+            if matches!(c, '.' | '!' | '?') {
+                sentence_end = true;
+            } else if c == '\n' || (c.is_whitespace() && sentence_end) {
+                capitalize = true;
+                sentence_end = false;
+            } else if !c.is_whitespace() && !matches!(c, '"' | ')' | '\'') {
+                // "e.g" or "3.5": the period was part of a word
+                sentence_end = false;
+            }
+        }
+
+        out
+    }
+}
+
+impl PostProcessor for Capitalize {
+    fn process(&self, text: &str, _context: &Context) -> String {
+        Self::capitalize(text, true)
+    }
+
+    /// A segment starts a sentence only if the one before ended it.
+    fn process_segments(&self, segments: &mut Vec<Segment>, _context: &Context) {
+        let mut at_start = true;
+        for segment in segments.iter_mut() {
+            segment.text = Self::capitalize(&segment.text, at_start);
+            at_start = segment
+                .text
+                .trim_end_matches([' ', '"', ')', '\''])
+                .ends_with(['.', '!', '?', '\n']);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capitalize(text: &str) -> String {
+        Capitalize.process(text, &Context::default())
+    }
+
+    fn segment(text: &str) -> Segment {
+        Segment {
+            start: 0,
+            end: 0,
+            text: text.to_string(),
+            confidence: 1.0,
+            words: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn capitalizes_sentences_and_lines() {
+        assert_eq!(
+            capitalize("hello. how are you? fine!"),
+            "Hello. How are you? Fine!"
+        );
+        assert_eq!(
+            capitalize("first line\nsecond line"),
+            "First line\nSecond line"
+        );
+        assert_eq!(
+            capitalize("she said \"stop.\" then left"),
+            "She said \"stop.\" Then left"
+        );
+    }
+
+    #[test]
+    fn ignores_periods_inside_words() {
+        assert_eq!(
+            capitalize("use e.g. version 3.5 now"),
+            "Use e.g. Version 3.5 now"
+        );
+        assert_eq!(
+            capitalize("see example.com for more"),
+            "See example.com for more"
+        );
+    }
+
+    #[test]
+    fn segments_continue_a_sentence() {
+        let mut segments = vec![
+            segment(" so this"),
+            segment(" goes on."),
+            segment(" next one"),
+        ];
+        Capitalize.process_segments(&mut segments, &Context::default());
+        let texts: Vec<&str> = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, [" So this", " goes on.", " Next one"]);
+    }
+}
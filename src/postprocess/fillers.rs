@@ -0,0 +1,208 @@
+use regex::Regex;
+
+use super::{Context, PostProcessor};
+use crate::config::FillersConfig;
+
+const SENTENCE_PUNCTUATION: &[char] = &[',', '.', ';', ':', '!', '?'];
+/// Words that are often doubled on purpose: "I had had enough", "I said
+/// that that was fine", "what it is is".
+const VALID_DOUBLES: &[&str] = &["had", "that", "is"];
+
+/// Removes filler words like "um" and "you know", and collapses words that
+/// are repeated right away, as in "the the".
+pub struct Fillers {
+    /// None when no filler words are configured
+    pattern: Option<Regex>,
+    collapse_repeats: bool,
+}
+
+impl Fillers {
+    pub fn new(config: &FillersConfig) -> Self {
+        let alternatives: Vec<String> = config
+            .words
+            .iter()
+            .map(|word| {
+                let words: Vec<String> = word.split_whitespace().map(regex::escape).collect();
+                words.join(r"\s+")
+            })
+            .filter(|pattern| !pattern.is_empty())
+            .collect();
+
+        // A comma after the filler belongs to it: "Um, so" becomes "So"
+        let pattern = (!alternatives.is_empty()).then(|| {
+            Regex::new(&format!(r"(?i)\b(?:{})\b,?", alternatives.join("|")))
+                .expect("valid filler pattern")
+        });
+
+        Self {
+            pattern,
+            collapse_repeats: config.collapse_repeats,
+        }
+    }
+}
+
+impl PostProcessor for Fillers {
+    fn process(&self, text: &str, _context: &Context) -> String {
+        let text = match &self.pattern {
+            Some(pattern) => remove_fillers(pattern, text),
+            None => text.to_string(),
+        };
+        if self.collapse_repeats {
+            collapse_repeats(&text)
+        } else {
+            text
+        }
+    }
+}
+
+fn remove_fillers(pattern: &Regex, text: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    let mut capitalize = false;
+
+    for found in pattern.find_iter(text) {
+        push_piece(&mut out, &text[last..found.start()], &mut capitalize);
+        // A capitalized filler started the sentence, so the next word does now
+        capitalize |= found.as_str().starts_with(char::is_uppercase);
+        last = found.end();
+    }
+    push_piece(&mut out, &text[last..], &mut capitalize);
+    // A filler at the very end leaves the space before it
+    if !text.ends_with(' ') {
+        out.truncate(out.trim_end_matches(' ').len());
+    }
+
+    out
+}
+
+/// Appends `piece`, collapsing the spaces left where a filler was taken out.
+fn push_piece(out: &mut String, piece: &str, capitalize: &mut bool) {
+    let mut piece = piece;
+    if out.is_empty() || out.ends_with(' ') {
+        piece = piece.trim_start_matches(' ');
+    }
+    // Punctuation that followed a filler: "it um." keeps the period, "Um. So" does not
+    if piece.starts_with(SENTENCE_PUNCTUATION) && (out.is_empty() || out.ends_with(' ')) {
+        if out.trim_end().is_empty() || out.trim_end().ends_with(['.', '!', '?']) {
+            piece = piece
+                .trim_start_matches(SENTENCE_PUNCTUATION)
+                .trim_start_matches(' ');
+        } else {
+            out.pop();
+        }
+    }
+    if piece.is_empty() {
+        return;
+    }
+
+    if *capitalize {
+        let mut chars = piece.chars();
+        if let Some(first) = chars.next() {
+            out.extend(first.to_uppercase());
+            piece = chars.as_str();
+        }
+        *capitalize = false;
+    }
+    out.push_str(piece);
+}
+
+/// Drops a word that repeats the one before it, ignoring case. Words
+/// separated by punctuation or a line break are kept, since "very, very" is
+/// usually meant, and so are the `VALID_DOUBLES`.
+fn collapse_repeats(text: &str) -> String {
+    let mut out = String::new();
+    let mut previous: Option<String> = None;
+    let mut separator = String::new();
+
+    for piece in text.split_inclusive(char::is_whitespace) {
+        let word = piece.trim_end();
+        let space = &piece[word.len()..];
+        if word.is_empty() {
+            separator.push_str(space);
+            continue;
+        }
+
+        let core = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let key = core.to_lowercase();
+        let repeated = !separator.contains('\n')
+            && core.chars().any(char::is_alphabetic)
+            && !VALID_DOUBLES.contains(&key.as_str())
+            && previous.as_deref() == Some(key.as_str());
+
+        if repeated {
+            // Keep the punctuation that ends the repeat: "the the." is "the."
+            out.push_str(&word[core.len()..]);
+        } else {
+            out.push_str(&separator);
+            out.push_str(word);
+        }
+
+        // Only a bare word can be repeated; "said, said" stays
+        previous = (core.len() == word.len()).then_some(key);
+        separator = space.to_string();
+    }
+    out.push_str(&separator);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fillers(collapse_repeats: bool) -> Fillers {
+        Fillers::new(&FillersConfig {
+            collapse_repeats,
+            ..FillersConfig::default()
+        })
+    }
+
+    fn clean(text: &str) -> String {
+        fillers(false).process(text, &Context::default())
+    }
+
+    #[test]
+    fn removes_fillers() {
+        assert_eq!(
+            clean("So, um, the build is green"),
+            "So, the build is green"
+        );
+        assert_eq!(clean("it is, you know, fine"), "it is, fine");
+        assert_eq!(clean("Hmm. Let me check"), "Let me check");
+        assert_eq!(clean("well uh"), "well");
+        assert_eq!(clean("summer umbrella"), "summer umbrella");
+    }
+
+    #[test]
+    fn capitalizes_after_a_leading_filler() {
+        assert_eq!(clean("Um, so we ship"), "So we ship");
+        assert_eq!(clean("Done. Uh, next one"), "Done. Next one");
+    }
+
+    #[test]
+    fn keeps_repeats_by_default() {
+        assert_eq!(clean("the the build"), "the the build");
+    }
+
+    #[test]
+    fn collapses_repeats() {
+        let collapse = |text: &str| fillers(true).process(text, &Context::default());
+        assert_eq!(collapse("the the build"), "the build");
+        assert_eq!(collapse("It It works"), "It works");
+        assert_eq!(collapse("ship it it."), "ship it.");
+        assert_eq!(collapse("very, very good"), "very, very good");
+        assert_eq!(collapse("said, said"), "said, said");
+        assert_eq!(collapse("go\ngo"), "go\ngo");
+    }
+
+    #[test]
+    fn keeps_grammatical_doubles() {
+        let collapse = |text: &str| fillers(true).process(text, &Context::default());
+        assert_eq!(collapse("I had had enough"), "I had had enough");
+        assert_eq!(
+            collapse("he said that that was fine"),
+            "he said that that was fine"
+        );
+        assert_eq!(collapse("what it is is a bug"), "what it is is a bug");
+    }
+}
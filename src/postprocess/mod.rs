@@ -0,0 +1,196 @@
+//! Rewrites applied to the transcribed text before it is shown and copied.
+//!
+//! Each rewrite is a `PostProcessor` stage; `[postprocess] stages` lists the
+//! ones to run, in order.
+
+use anyhow::Result;
+
+use crate::config::AppConfig;
+use crate::whisper::{Segment, TranscribeOutput};
+
+mod capitalize;
+mod fillers;
+mod punctuation;
+mod replace;
+mod stop_phrase;
+mod undo;
+
+pub use capitalize::Capitalize;
+pub use fillers::Fillers;
+pub use punctuation::SpokenPunctuation;
+pub use replace::{ReplacementRule, Replacements};
+pub use stop_phrase::StopPhrase;
+pub use undo::Undo;
+
+/// The stages `[postprocess] stages` can list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Removes the stop or submit phrase from the end of the final text
+    StopPhrase,
+    /// Removes the undo phrase and the sentence before it
+    Undo,
+    /// Removes `[fillers] words` and repeated words
+    Fillers,
+    /// Turns spoken commands like "comma" into punctuation
+    Punctuation,
+    /// Applies the `[replacements]` rules
+    Replacements,
+    /// Starts the text and every sentence with a capital
+    Capitalize,
+    /// A name this version does not know, e.g. from a newer config; skipped
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a stage knows about the text it rewrites.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Context<'a> {
+    /// The language of the text, when whisper detected it or translated
+    /// into English; the configured language otherwise
+    pub language: Option<&'a str>,
+    /// The text is the end of a finished recording rather than a realtime
+    /// pass
+    pub final_pass: bool,
+}
+
+/// One rewrite of the transcription.
+pub trait PostProcessor: Send {
+    fn process(&self, text: &str, context: &Context) -> String;
+
+    /// Rewrites the segments of the same transcription. Each is processed
+    /// like a text of its own unless the stage needs to see them together.
+    fn process_segments(&self, segments: &mut Vec<Segment>, context: &Context) {
+        for segment in segments.iter_mut() {
+            segment.text = self.process(&segment.text, context);
+        }
+    }
+
+    /// Called before each transcription, e.g. to pick up edited files.
+    fn refresh(&mut self) {}
+}
+
+/// The configured stages, run in order over every transcription.
+pub struct Pipeline {
+    stages: Vec<Box<dyn PostProcessor>>,
+}
+
+impl Pipeline {
+    pub fn new(config: &AppConfig, profile: Option<&str>) -> Result<Self> {
+        let mut stages: Vec<Box<dyn PostProcessor>> = Vec::new();
+
+        for stage in &config.postprocess.stages {
+            match stage {
+                Stage::StopPhrase => {
+                    let behavior = &config.behavior;
+                    let patterns: Vec<&str> = behavior
+                        .stop_phrase_enabled
+                        .then_some(behavior.stop_phrase_pattern.as_str())
+                        .into_iter()
+                        .chain(behavior.submit_phrase_pattern.as_deref())
+                        .collect();
+                    if patterns.is_empty() {
+                        log::debug!("stop and submit phrases are disabled, skipping their removal");
+                    } else {
+                        stages.push(Box::new(StopPhrase::new(&patterns)));
+                    }
+                }
+                Stage::Undo => match &config.behavior.undo_phrase_pattern {
+                    Some(pattern) => stages.push(Box::new(Undo::new(pattern))),
+                    None => log::debug!("no undo phrase configured, skipping the undo stage"),
+                },
+                Stage::Fillers => stages.push(Box::new(Fillers::new(&config.fillers))),
+                Stage::Punctuation => stages.push(Box::new(SpokenPunctuation::new(
+                    &config.whisper.language,
+                    config.punctuation.prefix.as_deref(),
+                ))),
+                Stage::Replacements => {
+                    stages.push(Box::new(Replacements::new(&config.replacements, profile)?));
+                }
+                Stage::Capitalize => stages.push(Box::new(Capitalize)),
+                Stage::Unknown => log::warn!("skipping unknown post-processing stage"),
+            }
+        }
+        log::debug!("post-processing stages: {:?}", config.postprocess.stages);
+
+        Ok(Self { stages })
+    }
+
+    /// Runs every stage over the text and the segments of `output`, then
+    /// drops segments left empty.
+    pub fn process(&mut self, output: &mut TranscribeOutput, context: &Context) {
+        for stage in &mut self.stages {
+            stage.refresh();
+        }
+
+        output.combined = output.combined.trim().to_string();
+        for stage in &self.stages {
+            output.combined = stage.process(&output.combined, context);
+            stage.process_segments(&mut output.segments, context);
+        }
+        output
+            .segments
+            .retain(|segment| !segment.text.trim().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(config: &AppConfig) -> Pipeline {
+        Pipeline::new(config, None).expect("pipeline")
+    }
+
+    fn run(pipeline: &mut Pipeline, text: &str, final_pass: bool) -> String {
+        let mut output = TranscribeOutput {
+            combined: text.to_string(),
+            ..Default::default()
+        };
+        let context = Context {
+            language: Some("en"),
+            final_pass,
+        };
+        pipeline.process(&mut output, &context);
+        output.combined
+    }
+
+    #[test]
+    fn runs_stages_in_the_configured_order() {
+        let mut config = AppConfig::default();
+        let text = "hello period how are you";
+
+        config.postprocess.stages = vec![Stage::Punctuation, Stage::Capitalize];
+        assert_eq!(
+            run(&mut pipeline(&config), text, true),
+            "Hello. How are you"
+        );
+
+        config.postprocess.stages = vec![Stage::Capitalize, Stage::Punctuation];
+        assert_eq!(
+            run(&mut pipeline(&config), text, true),
+            "Hello. how are you"
+        );
+    }
+
+    #[test]
+    fn skips_disabled_and_unknown_stages() {
+        let mut config = AppConfig {
+            postprocess: toml::from_str(r#"stages = ["stop_phrase", "undo", "shout"]"#)
+                .expect("stages"),
+            ..AppConfig::default()
+        };
+        assert_eq!(
+            config.postprocess.stages,
+            [Stage::StopPhrase, Stage::Undo, Stage::Unknown]
+        );
+        assert_eq!(pipeline(&config).stages.len(), 1);
+
+        // No stop or submit phrase, and no undo phrase
+        config.behavior.stop_phrase_enabled = false;
+        let mut pipeline = pipeline(&config);
+        assert!(pipeline.stages.is_empty());
+        let text = "Scratch that. Done, that's all";
+        assert_eq!(run(&mut pipeline, text, true), text);
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use super::{Context, PostProcessor};
+use crate::whisper::AUTO_LANGUAGE;
+
+/// Punctuation whisper tends to put around a spoken command, e.g. "comma,".
+const STRAY_PUNCTUATION: &[char] = &[',', '.', ';', ':', '!', '?'];
+
+/// How a symbol joins the words around it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Attach {
+    /// Follows the previous word, then a space: "," "." ")"
+    Left,
+    /// A space, then the next word follows it: "(" and an opening quote
+    Right,
+    /// Joins both neighbours: "-"
+    Join,
+    /// Whitespace that replaces the spaces around it: line breaks
+    Break,
+}
+
+use Attach::{Break, Join, Left, Right};
+
+type Table = &'static [(&'static str, &'static str, Attach)];
+
+const ENGLISH: Table = &[
+    ("comma", ",", Left),
+    ("period", ".", Left),
+    ("full stop", ".", Left),
+    ("question mark", "?", Left),
+    ("exclamation mark", "!", Left),
+    ("exclamation point", "!", Left),
+    ("colon", ":", Left),
+    ("semicolon", ";", Left),
+    ("ellipsis", "...", Left),
+    ("hyphen", "-", Join),
+    ("new line", "\n", Break),
+    ("newline", "\n", Break),
+    ("new paragraph", "\n\n", Break),
+    ("open quote", "\"", Right),
+    ("close quote", "\"", Left),
+    ("end quote", "\"", Left),
+    ("unquote", "\"", Left),
+    ("open parenthesis", "(", Right),
+    ("close parenthesis", ")", Left),
+];
+
+const GERMAN: Table = &[
+    ("komma", ",", Left),
+    ("punkt", ".", Left),
+    ("fragezeichen", "?", Left),
+    ("ausrufezeichen", "!", Left),
+    ("doppelpunkt", ":", Left),
+    ("semikolon", ";", Left),
+    ("bindestrich", "-", Join),
+    ("neue zeile", "\n", Break),
+    ("neuer absatz", "\n\n", Break),
+    ("anführungszeichen auf", "\"", Right),
+    ("anführungszeichen zu", "\"", Left),
+    ("klammer auf", "(", Right),
+    ("klammer zu", ")", Left),
+];
+
+const FRENCH: Table = &[
+    ("virgule", ",", Left),
+    ("point", ".", Left),
+    ("point d'interrogation", "?", Left),
+    ("point d'exclamation", "!", Left),
+    ("deux-points", ":", Left),
+    ("point-virgule", ";", Left),
+    ("trait d'union", "-", Join),
+    ("à la ligne", "\n", Break),
+    ("nouvelle ligne", "\n", Break),
+    ("nouveau paragraphe", "\n\n", Break),
+    ("ouvrez les guillemets", "\"", Right),
+    ("fermez les guillemets", "\"", Left),
+    ("ouvrez la parenthèse", "(", Right),
+    ("fermez la parenthèse", ")", Left),
+];
+
+const SPANISH: Table = &[
+    ("coma", ",", Left),
+    ("punto", ".", Left),
+    ("signo de interrogación", "?", Left),
+    ("signo de exclamación", "!", Left),
+    ("dos puntos", ":", Left),
+    ("punto y coma", ";", Left),
+    ("guion", "-", Join),
+    ("nueva línea", "\n", Break),
+    ("nuevo párrafo", "\n\n", Break),
+    ("abrir comillas", "\"", Right),
+    ("cerrar comillas", "\"", Left),
+    ("abrir paréntesis", "(", Right),
+    ("cerrar paréntesis", ")", Left),
+];
+
+const TABLES: &[(&str, Table)] = &[
+    ("en", ENGLISH),
+    ("de", GERMAN),
+    ("fr", FRENCH),
+    ("es", SPANISH),
+];
+
+/// The commands of one language, compiled.
+struct Commands {
+    pattern: Regex,
+    symbols: HashMap<String, (&'static str, Attach)>,
+}
+
+impl Commands {
+    fn new(table: Table, prefix: Option<&str>) -> Self {
+        // Longest first, so "new paragraph" wins over a shorter phrase it starts with
+        let mut phrases: Vec<&str> = table.iter().map(|(phrase, _, _)| *phrase).collect();
+        phrases.sort_by_key(|phrase| std::cmp::Reverse(phrase.len()));
+
+        let alternatives: Vec<String> = phrases
+            .iter()
+            .map(|phrase| phrase_pattern(phrase))
+            .collect();
+        let prefix = match prefix {
+            Some(prefix) => format!(r"\b{}\s+", phrase_pattern(prefix)),
+            None => String::new(),
+        };
+        let pattern = format!(
+            r"(?i){}\b(?P<phrase>{})\b[{}]*",
+            prefix,
+            alternatives.join("|"),
+            regex::escape(&STRAY_PUNCTUATION.iter().collect::<String>())
+        );
+
+        Self {
+            pattern: Regex::new(&pattern).expect("valid punctuation pattern"),
+            symbols: table
+                .iter()
+                .map(|(phrase, symbol, attach)| (normalize(phrase), (*symbol, *attach)))
+                .collect(),
+        }
+    }
+
+    fn apply(&self, text: &str) -> String {
+        let mut out = Output::default();
+        let mut last = 0;
+
+        for captures in self.pattern.captures_iter(text) {
+            let (Some(all), Some(phrase)) = (captures.get(0), captures.name("phrase")) else {
+                continue;
+            };
+            let Some(&(symbol, attach)) = self.symbols.get(&normalize(phrase.as_str())) else {
+                continue;
+            };
+
+            let before = &text[last..all.start()];
+            if attach == Left {
+                out.push_text(before.trim_end().trim_end_matches(STRAY_PUNCTUATION));
+            } else {
+                out.push_text(before);
+            }
+            out.push_symbol(symbol, attach);
+            last = all.end();
+        }
+        out.push_text(&text[last..]);
+
+        out.text
+    }
+}
+
+/// Joins text and symbols with the spacing each symbol asks for.
+#[derive(Default)]
+struct Output {
+    text: String,
+    /// The next piece follows without a space
+    glued: bool,
+}
+
+impl Output {
+    fn push_text(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        if !self.text.is_empty() && !self.glued {
+            self.text.push(' ');
+        }
+        self.text.push_str(text);
+        self.glued = false;
+    }
+
+    fn push_symbol(&mut self, symbol: &str, attach: Attach) {
+        if attach == Right && !self.text.is_empty() && !self.glued {
+            self.text.push(' ');
+        }
+        self.text.push_str(symbol);
+        self.glued = attach != Left;
+    }
+}
+
+/// Matches the words of `phrase` with any spacing or hyphens between them
+/// and either kind of apostrophe.
+fn phrase_pattern(phrase: &str) -> String {
+    phrase
+        .split([' ', '-'])
+        .map(|word| regex::escape(word).replace('\'', "['’]"))
+        .collect::<Vec<_>>()
+        .join(r"[\s-]+")
+}
+
+fn normalize(phrase: &str) -> String {
+    phrase
+        .to_lowercase()
+        .replace('’', "'")
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Turns spoken commands like "comma" or "new paragraph" into the symbols
+/// and whitespace they name, using the command table of the text's language.
+///
+/// Commands are ordinary words, so without a prefix "the trial period"
+/// loses its last word to a ".".
+pub struct SpokenPunctuation {
+    language: String,
+    commands: HashMap<&'static str, Commands>,
+}
+
+impl SpokenPunctuation {
+    /// `language` is the table used when a pass does not report the
+    /// language it detected. With a `prefix` only commands said after it
+    /// are converted.
+    pub fn new(language: &str, prefix: Option<&str>) -> Self {
+        if language != AUTO_LANGUAGE && !TABLES.iter().any(|(code, _)| *code == language) {
+            log::warn!(
+                "no spoken punctuation commands for language \"{}\", supported are {}",
+                language,
+                languages().join(", ")
+            );
+        }
+
+        Self {
+            language: language.to_string(),
+            commands: TABLES
+                .iter()
+                .map(|(code, table)| (*code, Commands::new(table, prefix)))
+                .collect(),
+        }
+    }
+}
+
+impl PostProcessor for SpokenPunctuation {
+    /// Converts the commands with the table of the context's language, or
+    /// of the configured one. Text in a language without a table is
+    /// returned as is.
+    fn process(&self, text: &str, context: &Context) -> String {
+        let language = context.language.unwrap_or(&self.language);
+        match self.commands.get(language) {
+            Some(commands) => commands.apply(text),
+            None => text.to_string(),
+        }
+    }
+}
+
+/// Language codes that have a command table.
+fn languages() -> Vec<&'static str> {
+    TABLES.iter().map(|(code, _)| *code).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(text: &str, language: &str) -> String {
+        let context = Context {
+            language: Some(language),
+            final_pass: true,
+        };
+        SpokenPunctuation::new("en", None).process(text, &context)
+    }
+
+    #[test]
+    fn left_attaching_marks() {
+        assert_eq!(
+            convert("hello comma world exclamation mark", "en"),
+            "hello, world!"
+        );
+        assert_eq!(
+            convert("is it done question mark yes full stop", "en"),
+            "is it done? yes."
+        );
+        assert_eq!(
+            convert("open quote hi close quote she said", "en"),
+            "\"hi\" she said"
+        );
+        assert_eq!(
+            convert("see open parenthesis below close parenthesis", "en"),
+            "see (below)"
+        );
+        assert_eq!(convert("well hyphen known", "en"), "well-known");
+    }
+
+    #[test]
+    fn stray_punctuation_collapses() {
+        assert_eq!(convert("Hello, comma", "en"), "Hello,");
+        assert_eq!(convert("Done. Period.", "en"), "Done.");
+        assert_eq!(convert("Really, question mark?", "en"), "Really?");
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(convert("first new line second", "en"), "first\nsecond");
+        assert_eq!(
+            convert("Dear Anna comma new paragraph thanks", "en"),
+            "Dear Anna,\n\nthanks"
+        );
+    }
+
+    #[test]
+    fn other_languages() {
+        assert_eq!(
+            convert("Hallo Komma wie geht's Fragezeichen neue Zeile gut", "de"),
+            "Hallo, wie geht's?\ngut"
+        );
+        assert_eq!(convert("bonjour virgule ça va", "fr"), "bonjour, ça va");
+        assert_eq!(convert("hola coma qué tal", "es"), "hola, qué tal");
+        // Without a table the text is left alone
+        assert_eq!(convert("ciao virgola", "it"), "ciao virgola");
+    }
+
+    #[test]
+    fn prefix_keeps_ordinary_words() {
+        let context = Context::default();
+        assert_eq!(convert("the trial period ended", "en"), "the trial. ended");
+
+        let punctuation = SpokenPunctuation::new("en", Some("say"));
+        assert_eq!(
+            punctuation.process("the trial period ended say period", &context),
+            "the trial period ended."
+        );
+    }
+}
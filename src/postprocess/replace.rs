@@ -0,0 +1,473 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context as _, Result, bail};
+use regex::{Captures, Regex};
+
+use super::{Context, PostProcessor};
+use crate::config::{AppConfig, ReplacementsConfig};
+
+/// One rewrite, as written in `[replacements]` or a replacement file.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ReplacementRule {
+    /// Text to look for, ignoring case, or a regex with `regex = true`
+    pub from: String,
+    /// The replacement; regex rules can refer to groups as `$1` or `${name}`
+    pub to: String,
+    #[serde(default)]
+    pub regex: bool,
+    /// Only replace `from` where it is not part of a longer word
+    #[serde(default = "enabled")]
+    pub whole_word: bool,
+    /// Capitalize or upper-case the replacement like the text it replaces
+    #[serde(default = "enabled")]
+    pub preserve_case: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// The layout of a TOML replacement file.
+#[derive(serde::Deserialize)]
+struct RuleFile {
+    rules: Vec<ReplacementRule>,
+}
+
+struct Rule {
+    pattern: Regex,
+    to: String,
+    regex: bool,
+    preserve_case: bool,
+}
+
+impl Rule {
+    fn new(rule: &ReplacementRule) -> Result<Self> {
+        let pattern = if rule.regex {
+            rule.from.clone()
+        } else {
+            // Whisper may split or join the words differently
+            let words: Vec<String> = rule.from.split_whitespace().map(regex::escape).collect();
+            format!(r"(?i){}", words.join(r"\s+"))
+        };
+        if pattern.is_empty() || pattern == "(?i)" {
+            bail!("Replacement for \"{}\" has nothing to look for", rule.to);
+        }
+        let pattern = if rule.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+
+        Ok(Self {
+            pattern: Regex::new(&pattern)
+                .with_context(|| format!("Invalid replacement pattern \"{}\"", rule.from))?,
+            to: rule.to.clone(),
+            regex: rule.regex,
+            preserve_case: rule.preserve_case,
+        })
+    }
+
+    fn apply(&self, text: &str) -> String {
+        self.pattern
+            .replace_all(text, |captures: &Captures| {
+                let mut to = String::new();
+                if self.regex {
+                    captures.expand(&self.to, &mut to);
+                } else {
+                    to.push_str(&self.to);
+                }
+                if self.preserve_case {
+                    match_case(&captures[0], &to)
+                } else {
+                    to
+                }
+            })
+            .into_owned()
+    }
+}
+
+/// Upper-cases `to` when `matched` is all capitals, capitalizes it when
+/// `matched` starts with a capital, and leaves it as written otherwise.
+fn match_case(matched: &str, to: &str) -> String {
+    let mut letters = matched.chars().filter(|c| c.is_alphabetic());
+    let Some(first) = letters.next() else {
+        return to.to_string();
+    };
+
+    let rest: Vec<char> = letters.collect();
+    if first.is_uppercase() && !rest.is_empty() && rest.iter().all(|c| c.is_uppercase()) {
+        to.to_uppercase()
+    } else if first.is_uppercase() {
+        let mut chars = to.chars();
+        match chars.next() {
+            Some(c) => c.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    } else {
+        to.to_string()
+    }
+}
+
+/// A file the rules were read from and when it was last changed.
+struct Source {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl Source {
+    fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Self { path, modified }
+    }
+
+    fn changed(&self) -> bool {
+        modified(&self.path) != self.modified
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Rewrites mis-heard words and phrases with the rules from `[replacements]`
+/// and its `file`, in the order they are listed.
+///
+/// The rules are read again whenever speak.toml or the replacement file
+/// changes, so edits apply to the next transcription without a restart.
+pub struct Replacements {
+    rules: Vec<Rule>,
+    /// speak.toml, then the replacement file
+    sources: Vec<Source>,
+    /// The profile to apply when speak.toml is read again
+    profile: Option<String>,
+}
+
+impl Replacements {
+    pub fn new(config: &ReplacementsConfig, profile: Option<&str>) -> Result<Self> {
+        Self::load(config, AppConfig::get_config_path()?, profile)
+    }
+
+    /// The rules of `config`, which was read from `config_path`.
+    fn load(
+        config: &ReplacementsConfig,
+        config_path: PathBuf,
+        profile: Option<&str>,
+    ) -> Result<Self> {
+        let mut rules = config
+            .rules
+            .iter()
+            .map(Rule::new)
+            .collect::<Result<Vec<_>>>()?;
+
+        // A relative file is next to speak.toml, wherever speak-rs runs
+        let file = config.file.as_ref().map(|file| match config_path.parent() {
+            Some(dir) => dir.join(file),
+            None => file.clone(),
+        });
+        let mut sources = vec![Source::new(config_path)];
+        if let Some(path) = &file {
+            sources.push(Source::new(path.clone()));
+            for rule in read_rule_file(path)? {
+                rules.push(Rule::new(&rule).with_context(|| {
+                    format!("Invalid rule in replacement file {}", path.display())
+                })?);
+            }
+        }
+        if !rules.is_empty() {
+            log::debug!("loaded {} replacement rules", rules.len());
+        }
+
+        Ok(Self {
+            rules,
+            sources,
+            profile: profile.map(str::to_string),
+        })
+    }
+
+    /// Reads the rules again if speak.toml or the replacement file changed.
+    /// Invalid rules are reported and the previous ones kept.
+    fn reload_if_changed(&mut self) {
+        if !self.sources.iter().any(Source::changed) {
+            return;
+        }
+
+        let config_path = self.sources[0].path.clone();
+        let profile = self.profile.clone();
+        let reloaded = AppConfig::load(&config_path, profile.as_deref())
+            .and_then(|config| Self::load(&config.replacements, config_path, profile.as_deref()));
+        match reloaded {
+            Ok(replacements) => {
+                log::info!("reloaded {} replacement rules", replacements.rules.len());
+                *self = replacements;
+            }
+            Err(e) => {
+                log::warn!("keeping the previous replacements: {:#}", e);
+                // Wait for the next change instead of failing on every pass
+                for source in &mut self.sources {
+                    source.modified = modified(&source.path);
+                }
+            }
+        }
+    }
+}
+
+impl PostProcessor for Replacements {
+    /// Applies every rule, in order.
+    fn process(&self, text: &str, _context: &Context) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            text = rule.apply(&text);
+        }
+        text
+    }
+
+    fn refresh(&mut self) {
+        self.reload_if_changed();
+    }
+}
+
+/// Reads a TOML file with a `rules` list, or a CSV file with the columns
+/// `from,to,regex,whole_word,preserve_case` of which only the first two are
+/// required.
+fn read_rule_file(path: &Path) -> Result<Vec<ReplacementRule>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read replacement file {}", path.display()))?;
+
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    let rules = if is_csv {
+        parse_csv(&contents)
+    } else {
+        toml::from_str::<RuleFile>(&contents)
+            .map(|file| file.rules)
+            .map_err(anyhow::Error::from)
+    };
+
+    rules.with_context(|| format!("Failed to parse replacement file {}", path.display()))
+}
+
+fn parse_csv(contents: &str) -> Result<Vec<ReplacementRule>> {
+    let mut rules = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let fields = csv_fields(line);
+        // An optional header row
+        if rules.is_empty() && fields.first().is_some_and(|field| field.trim() == "from") {
+            continue;
+        }
+
+        let line_number = index + 1;
+        let flag = |column: usize, default: bool| -> Result<bool> {
+            match fields.get(column).map(|field| field.trim().to_lowercase()) {
+                None => Ok(default),
+                Some(value) => match value.as_str() {
+                    "" => Ok(default),
+                    "true" | "yes" | "1" => Ok(true),
+                    "false" | "no" | "0" => Ok(false),
+                    _ => bail!(
+                        "line {}: expected true or false, got \"{}\"",
+                        line_number,
+                        value
+                    ),
+                },
+            }
+        };
+        let (Some(from), Some(to)) = (fields.first(), fields.get(1)) else {
+            bail!("line {}: expected at least from,to", line_number);
+        };
+
+        rules.push(ReplacementRule {
+            from: from.trim().to_string(),
+            to: to.trim().to_string(),
+            regex: flag(2, false)?,
+            whole_word: flag(3, true)?,
+            preserve_case: flag(4, true)?,
+        });
+    }
+
+    Ok(rules)
+}
+
+/// Splits a CSV line into fields; quoted fields may contain commas and
+/// doubled quotes.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn rule(from: &str, to: &str) -> ReplacementRule {
+        ReplacementRule {
+            from: from.to_string(),
+            to: to.to_string(),
+            regex: false,
+            whole_word: true,
+            preserve_case: true,
+        }
+    }
+
+    fn apply(rule: ReplacementRule, text: &str) -> String {
+        Rule::new(&rule).expect("valid rule").apply(text)
+    }
+
+    /// An empty directory of its own for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("speak-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("test directory");
+        dir
+    }
+
+    /// Writes `contents` and moves the modification time forward, since a
+    /// quick rewrite may keep the same timestamp.
+    fn rewrite(path: &Path, contents: &str) {
+        let modified = modified(path).unwrap_or(SystemTime::now());
+        fs::write(path, contents).expect("write");
+        let file = fs::File::options().write(true).open(path).expect("open");
+        file.set_modified(modified + Duration::from_secs(2))
+            .expect("set modified");
+    }
+
+    #[test]
+    fn literal_rules_ignore_case_and_spacing() {
+        let rule = ReplacementRule {
+            preserve_case: false,
+            ..rule("cube control", "kubectl")
+        };
+        assert_eq!(
+            apply(rule, "run Cube  Control get pods"),
+            "run kubectl get pods"
+        );
+    }
+
+    #[test]
+    fn whole_word() {
+        assert_eq!(
+            apply(rule("post", "mail"), "post the postcard"),
+            "mail the postcard"
+        );
+
+        let rule = ReplacementRule {
+            whole_word: false,
+            ..rule("post", "mail")
+        };
+        assert_eq!(apply(rule, "post the postcard"), "mail the mailcard");
+    }
+
+    #[test]
+    fn regex_rules_expand_groups() {
+        let rule = ReplacementRule {
+            regex: true,
+            ..rule(r"(\d+) percent", "$1%")
+        };
+        assert_eq!(apply(rule, "up 20 percent today"), "up 20% today");
+    }
+
+    #[test]
+    fn preserve_case() {
+        let postgres = rule("post gress", "postgres");
+        assert_eq!(
+            apply(postgres.clone(), "Post gress is up"),
+            "Postgres is up"
+        );
+        assert_eq!(
+            apply(postgres.clone(), "POST GRESS is up"),
+            "POSTGRES is up"
+        );
+        assert_eq!(apply(postgres, "use post gress"), "use postgres");
+
+        let rule = ReplacementRule {
+            preserve_case: false,
+            ..rule("post gress", "postgres")
+        };
+        assert_eq!(apply(rule, "Post gress is up"), "postgres is up");
+    }
+
+    #[test]
+    fn csv_with_and_without_header() {
+        let with_header = "from,to,regex,whole_word,preserve_case\n\
+                           # comment\n\
+                           cube control,kubectl,,,false\n\
+                           \"one, two\",\"say \"\"hi\"\"\"\n";
+        let rules = parse_csv(with_header).expect("csv");
+        assert_eq!(
+            rules,
+            [
+                ReplacementRule {
+                    preserve_case: false,
+                    ..rule("cube control", "kubectl")
+                },
+                rule("one, two", "say \"hi\""),
+            ]
+        );
+
+        let rules = parse_csv("post gress,Postgres\n(\\d+) percent,$1%,true\n").expect("csv");
+        assert_eq!(
+            rules,
+            [
+                rule("post gress", "Postgres"),
+                ReplacementRule {
+                    regex: true,
+                    ..rule(r"(\d+) percent", "$1%")
+                },
+            ]
+        );
+
+        assert!(parse_csv("only one column").is_err());
+        assert!(parse_csv("a,b,maybe").is_err());
+    }
+
+    #[test]
+    fn reloads_changed_files() {
+        let dir = test_dir("replacements");
+        let config_path = dir.join("speak.toml");
+        let rules_path = dir.join("rules.csv");
+        fs::write(&config_path, "[replacements]\nfile = \"rules.csv\"\n").expect("write");
+        fs::write(&rules_path, "teh,the\n").expect("write");
+
+        let config = AppConfig::load(&config_path, None).expect("config");
+        let mut replacements =
+            Replacements::load(&config.replacements, config_path, None).expect("replacements");
+        let context = Context::default();
+        assert_eq!(replacements.process("teh end", &context), "the end");
+
+        rewrite(&rules_path, "teh,a\n");
+        replacements.refresh();
+        assert_eq!(replacements.process("teh end", &context), "a end");
+
+        // Broken rules keep the previous ones
+        rewrite(&rules_path, "teh,a,maybe\n");
+        replacements.refresh();
+        assert_eq!(replacements.process("teh end", &context), "a end");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,133 @@
+use regex::Regex;
+
+use super::{Context, PostProcessor};
+use crate::whisper::Segment;
+
+/// Removes the stop or submit phrase that ended a recording from the final
+/// text. Realtime text keeps it, so the session can still detect it.
+pub struct StopPhrase {
+    /// None when a configured pattern is not a valid regex
+    pattern: Option<Regex>,
+}
+
+impl StopPhrase {
+    pub fn new(patterns: &[&str]) -> Self {
+        let alternatives: Vec<String> = patterns
+            .iter()
+            .map(|pattern| format!("(?:{})$", pattern))
+            .collect();
+        let pattern = Regex::new(&alternatives.join("|"))
+            .inspect_err(|e| log::warn!("invalid stop phrase pattern: {}", e))
+            .ok();
+        Self { pattern }
+    }
+
+    /// The text before the stop phrase, or None when `text` does not end
+    /// with it.
+    fn remove(&self, text: &str) -> Option<String> {
+        let found = self.pattern.as_ref()?.find(text)?;
+        Some(text[..found.start()].trim_end().to_string())
+    }
+}
+
+impl PostProcessor for StopPhrase {
+    fn process(&self, text: &str, context: &Context) -> String {
+        if !context.final_pass {
+            return text.to_string();
+        }
+        self.remove(text).unwrap_or_else(|| text.to_string())
+    }
+
+    /// Removes the phrase from the end of the last segment, dropping the
+    /// segment when nothing else is left in it. Timed words beyond what
+    /// remains of the text are dropped with it.
+    fn process_segments(&self, segments: &mut Vec<Segment>, context: &Context) {
+        if !context.final_pass {
+            return;
+        }
+        let Some(last) = segments.last_mut() else {
+            return;
+        };
+        let Some(text) = self.remove(last.text.trim_end()) else {
+            return;
+        };
+
+        if text.trim().is_empty() {
+            segments.pop();
+        } else {
+            last.words.truncate(text.split_whitespace().count());
+            last.text = text;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::whisper::Word;
+
+    const FINAL: Context = Context {
+        language: None,
+        final_pass: true,
+    };
+
+    fn stop_phrase() -> StopPhrase {
+        StopPhrase::new(&[r"(?i)that'?s all\.?$", r"(?i)send it\.?$"])
+    }
+
+    fn segment(text: &str) -> Segment {
+        let words = text
+            .split_whitespace()
+            .map(|word| Word {
+                start: 0,
+                end: 0,
+                text: word.to_string(),
+                probability: 1.0,
+            })
+            .collect();
+        Segment {
+            start: 0,
+            end: 0,
+            text: text.to_string(),
+            confidence: 1.0,
+            words,
+        }
+    }
+
+    #[test]
+    fn removes_the_phrase_from_the_final_text() {
+        let stop_phrase = stop_phrase();
+        assert_eq!(
+            stop_phrase.process("Done for today. That's all.", &FINAL),
+            "Done for today."
+        );
+        assert_eq!(
+            stop_phrase.process("Reply yes, send it", &FINAL),
+            "Reply yes,"
+        );
+        assert_eq!(
+            stop_phrase.process("That's all we know.", &FINAL),
+            "That's all we know."
+        );
+    }
+
+    #[test]
+    fn keeps_the_phrase_in_realtime_text() {
+        let text = "Done for today. That's all.";
+        assert_eq!(stop_phrase().process(text, &Context::default()), text);
+    }
+
+    #[test]
+    fn removes_the_phrase_from_the_last_segment() {
+        let mut segments = vec![segment(" Done for today."), segment(" Bye now, thats all.")];
+        stop_phrase().process_segments(&mut segments, &FINAL);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].text, " Bye now,");
+        assert_eq!(segments[1].words.len(), 2);
+
+        let mut segments = vec![segment(" Done for today."), segment(" That's all.")];
+        stop_phrase().process_segments(&mut segments, &FINAL);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].text, " Done for today.");
+    }
+}
@@ -0,0 +1,229 @@
+use std::ops::Range;
+
+use regex::Regex;
+
+use super::{Context, PostProcessor};
+use crate::whisper::Segment;
+
+const SENTENCE_END: &[char] = &['.', '!', '?'];
+
+/// Takes back what was said before the undo phrase: the unfinished sentence
+/// it interrupts, or the sentence before it when the phrase starts one.
+/// "Meet on Monday. Scratch that. Meet on Friday." becomes "Meet on Friday."
+pub struct Undo {
+    /// None when the configured pattern is not a valid regex
+    pattern: Option<Regex>,
+}
+
+impl Undo {
+    pub fn new(pattern: &str) -> Self {
+        let pattern = Regex::new(pattern)
+            .inspect_err(|e| log::warn!("invalid undo phrase pattern: {}", e))
+            .ok();
+        Self { pattern }
+    }
+
+    /// The first undo phrase in `text` together with the sentence it takes
+    /// back.
+    fn find(&self, text: &str) -> Option<Range<usize>> {
+        // An empty match would never go away
+        let found = self
+            .pattern
+            .as_ref()?
+            .find(text)
+            .filter(|found| !found.is_empty())?;
+        Some(sentence_before(&text[..found.start()])..found.end())
+    }
+}
+
+impl PostProcessor for Undo {
+    fn process(&self, text: &str, _context: &Context) -> String {
+        let mut text = text.to_string();
+        while let Some(range) = self.find(&text) {
+            text = remove(&text, range);
+        }
+        text
+    }
+
+    /// The segments are undone as one text, since the sentence taken back
+    /// may have started in an earlier segment.
+    fn process_segments(&self, segments: &mut Vec<Segment>, _context: &Context) {
+        loop {
+            let text: String = segments
+                .iter()
+                .map(|segment| segment.text.as_str())
+                .collect();
+            let Some(range) = self.find(&text) else {
+                return;
+            };
+
+            let mut offset = 0;
+            for segment in segments.iter_mut() {
+                let len = segment.text.len();
+                let start = range.start.clamp(offset, offset + len) - offset;
+                let end = range.end.clamp(offset, offset + len) - offset;
+                if start < end {
+                    cut(segment, start..end);
+                }
+                offset += len;
+            }
+        }
+    }
+}
+
+/// Where the sentence the undo phrase takes back starts, given the text
+/// before the phrase.
+fn sentence_before(text: &str) -> usize {
+    let mut trimmed = text.trim_end();
+    // The phrase starts a sentence, so the one before it goes
+    if trimmed.ends_with(SENTENCE_END) || text[trimmed.len()..].contains('\n') {
+        trimmed = trimmed.trim_end_matches(SENTENCE_END);
+    }
+    sentence_start(trimmed)
+}
+
+/// The start of the last sentence in `text`: after a line break, or after
+/// ".", "!" or "?" followed by whitespace.
+fn sentence_start(text: &str) -> usize {
+    let mut start = 0;
+    let mut sentence_end = false;
+
+    for (i, c) in text.char_indices() {
+        if SENTENCE_END.contains(&c) {
+            sentence_end = true;
+        } else if c == '\n' || (c.is_whitespace() && sentence_end) {
+            start = i + c.len_utf8();
+            sentence_end = false;
+        } else if !c.is_whitespace() {
+            sentence_end = false;
+        }
+    }
+
+    start
+}
+
+/// Removes `range` from the text of `segment` along with the timed words
+/// in it.
+fn cut(segment: &mut Segment, range: Range<usize>) {
+    let first = segment.text[..range.start].split_whitespace().count();
+    let count = segment.text[range.clone()].split_whitespace().count();
+    let end = (first + count).min(segment.words.len());
+    if first < end {
+        segment.words.drain(first..end);
+    }
+    segment.text = remove(&segment.text, range);
+}
+
+/// `text` without `range`, joining what is left with a single space.
+fn remove(text: &str, range: Range<usize>) -> String {
+    let head = &text[..range.start];
+    let tail = &text[range.end..];
+    if head.trim().is_empty() {
+        // Keep the space a segment starts with
+        let indent = &text[..text.len() - text.trim_start().len()];
+        return format!("{}{}", indent, tail.trim_start());
+    }
+
+    let mut out = head.trim_end_matches(' ').to_string();
+    if out.ends_with('\n') {
+        out.push_str(tail.trim_start());
+    } else {
+        let tail = tail.trim_start_matches(' ');
+        if !tail.is_empty() && !tail.starts_with('\n') {
+            out.push(' ');
+        }
+        out.push_str(tail);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::whisper::Word;
+
+    fn undo() -> Undo {
+        Undo::new(r"(?i)scratch that[.!]?")
+    }
+
+    fn process(text: &str) -> String {
+        undo().process(text, &Context::default())
+    }
+
+    fn segment(text: &str) -> Segment {
+        let words = text
+            .split_whitespace()
+            .map(|word| Word {
+                start: 0,
+                end: 0,
+                text: word.to_string(),
+                probability: 1.0,
+            })
+            .collect();
+        Segment {
+            start: 0,
+            end: 0,
+            text: text.to_string(),
+            confidence: 1.0,
+            words,
+        }
+    }
+
+    #[test]
+    fn removes_only_the_last_sentence() {
+        assert_eq!(
+            process("Meet on Monday. Scratch that. Meet on Friday."),
+            "Meet on Friday."
+        );
+        assert_eq!(
+            process("Lunch is booked. Meet on Monday. Scratch that. Meet on Friday."),
+            "Lunch is booked. Meet on Friday."
+        );
+    }
+
+    #[test]
+    fn removes_an_unfinished_sentence() {
+        assert_eq!(
+            process("First done. Meet on scratch that Friday."),
+            "First done. Friday."
+        );
+    }
+
+    #[test]
+    fn removes_one_sentence_per_phrase() {
+        assert_eq!(
+            process("One. Two. Scratch that. Scratch that. Three."),
+            "Three."
+        );
+    }
+
+    #[test]
+    fn stops_at_a_line_break() {
+        assert_eq!(
+            process("Header\nLine one. Scratch that.\nLine two"),
+            "Header\nLine two"
+        );
+    }
+
+    #[test]
+    fn undoes_across_segments() {
+        let mut segments = vec![
+            segment(" Meet on Monday."),
+            segment(" Scratch that. Friday."),
+        ];
+        undo().process_segments(&mut segments, &Context::default());
+
+        assert!(segments[0].text.trim().is_empty());
+        assert!(segments[0].words.is_empty());
+        assert_eq!(segments[1].text, " Friday.");
+        assert_eq!(segments[1].words.len(), 1);
+    }
+
+    #[test]
+    fn ignores_empty_and_invalid_patterns() {
+        let text = "Meet on Monday. Scratch that.";
+        let context = Context::default();
+        assert_eq!(Undo::new("(scratch that)?").process(text, &context), text);
+        assert_eq!(Undo::new("(scratch").process(text, &context), text);
+    }
+}
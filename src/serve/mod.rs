@@ -0,0 +1,472 @@
+use std::fmt::Display;
+use std::io::Read;
+
+use anyhow::Result;
+use log::info;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    capture,
+    config::AppConfig,
+    downmix::{DownmixStrategy, downmix},
+    error::SpeakError,
+    export,
+    postprocess::{self, Pipeline},
+    whisper,
+};
+
+mod multipart;
+
+const TRANSCRIPTIONS_PATH: &str = "/v1/audio/transcriptions";
+const TRANSLATIONS_PATH: &str = "/v1/audio/translations";
+
+/// Uploads above this size are rejected, matching the OpenAI API limit.
+const MAX_UPLOAD_BYTES: u64 = 25 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ResponseFormat {
+    Text,
+    Json,
+    VerboseJson,
+    Srt,
+    Vtt,
+}
+
+impl ResponseFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "text" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            "verbose_json" => Some(Self::VerboseJson),
+            "srt" => Some(Self::Srt),
+            "vtt" => Some(Self::Vtt),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Json | Self::VerboseJson => "application/json",
+            Self::Vtt => "text/vtt; charset=utf-8",
+            Self::Text | Self::Srt => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Display) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    fn bad_request(message: impl Display) -> Self {
+        Self::new(400, message)
+    }
+}
+
+impl From<SpeakError> for ApiError {
+    fn from(error: SpeakError) -> Self {
+        let status = match error {
+            SpeakError::AudioTooShort | SpeakError::UnknownLanguage(_) => 400,
+            _ => 500,
+        };
+        Self::new(status, error)
+    }
+}
+
+struct Upload {
+    audio: capture::AudioBuffer,
+    format: ResponseFormat,
+    /// `timestamp_granularities[]` asked for words
+    word_timestamps: bool,
+    /// Replaces the configured language for this request
+    language: Option<String>,
+    /// Text the audio is expected to continue from
+    prompt: Option<String>,
+}
+
+/// Serves OpenAI-compatible transcription and translation endpoints backed
+/// by the local model. The model is loaded once and requests are handled one at a time.
+pub fn serve(config: AppConfig, profile: Option<&str>, addr: &str) -> Result<()> {
+    let language = config.whisper.language.clone();
+    let downmix = config.capture.downmix;
+    let mut pipeline = Pipeline::new(&config, profile)?;
+    let transcriber =
+        whisper::load(config.whisper).inspect_err(|e| log::error!("{}", e.recovery()))?;
+
+    let server =
+        Server::http(addr).map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", addr, e))?;
+    info!(
+        "serving transcriptions on http://{}{}",
+        server.server_addr(),
+        TRANSCRIPTIONS_PATH
+    );
+
+    for request in server.incoming_requests() {
+        handle(
+            request,
+            transcriber.as_ref(),
+            &mut pipeline,
+            &language,
+            downmix,
+        );
+    }
+
+    Ok(())
+}
+
+/// Answers one request, with an OpenAI-style error body when it fails.
+fn handle(
+    mut request: Request,
+    transcriber: &dyn whisper::Transcriber,
+    pipeline: &mut Pipeline,
+    language: &str,
+    downmix: DownmixStrategy,
+) {
+    log::debug!("{} {}", request.method(), request.url());
+
+    let path = request.url().split('?').next().unwrap_or_default();
+    let task = match path {
+        TRANSCRIPTIONS_PATH => Some(whisper::Task::Transcribe),
+        TRANSLATIONS_PATH => Some(whisper::Task::Translate),
+        _ => None,
+    };
+    let result = if let Some(task) = task {
+        if *request.method() != Method::Post {
+            Err(ApiError::new(405, "Only POST is supported"))
+        } else {
+            read_upload(&mut request, downmix)
+                .and_then(|upload| transcribe(transcriber, pipeline, language, task, upload))
+        }
+    } else {
+        Err(ApiError::new(404, format!("Unknown path {}", path)))
+    };
+
+    let response = match result {
+        Ok((format, body)) => {
+            Response::from_string(body).with_header(content_type(format.content_type()))
+        }
+        Err(error) => {
+            log::warn!("request failed ({}): {}", error.status, error.message);
+            let kind = if error.status < 500 {
+                "invalid_request_error"
+            } else {
+                "server_error"
+            };
+            let body = json!({
+                "error": {
+                    "message": error.message,
+                    "type": kind,
+                    "param": null,
+                    "code": null,
+                }
+            });
+            Response::from_string(body.to_string())
+                .with_status_code(error.status)
+                .with_header(content_type("application/json"))
+        }
+    };
+
+    if let Err(e) = request.respond(response) {
+        log::warn!("failed to send response: {}", e);
+    }
+}
+
+/// Reads the multipart form and decodes the uploaded `file` field.
+fn read_upload(
+    request: &mut Request,
+    downmix_strategy: DownmixStrategy,
+) -> Result<Upload, ApiError> {
+    let boundary = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Content-Type"))
+        .and_then(|h| multipart::boundary(h.value.as_str()))
+        .ok_or_else(|| ApiError::bad_request("Expected a multipart/form-data body"))?;
+
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_UPLOAD_BYTES + 1)
+        .read_to_end(&mut body)
+        .map_err(ApiError::bad_request)?;
+    if body.len() as u64 > MAX_UPLOAD_BYTES {
+        return Err(ApiError::new(
+            413,
+            format!("Upload exceeds {} bytes", MAX_UPLOAD_BYTES),
+        ));
+    }
+
+    let mut format = ResponseFormat::Json;
+    let mut word_timestamps = false;
+    let mut language = None;
+    let mut prompt = None;
+    let mut file = None;
+    for part in multipart::parse(&body, &boundary).map_err(ApiError::bad_request)? {
+        match part.name.as_str() {
+            "file" => file = Some(part),
+            "response_format" => {
+                let value = part.text().map_err(ApiError::bad_request)?.trim();
+                format = ResponseFormat::parse(value).ok_or_else(|| {
+                    ApiError::bad_request(format!("Unsupported response_format {}", value))
+                })?;
+            }
+            "timestamp_granularities[]" | "timestamp_granularities" => {
+                let value = part.text().map_err(ApiError::bad_request)?.trim();
+                match value {
+                    "word" => word_timestamps = true,
+                    "segment" => {}
+                    _ => {
+                        return Err(ApiError::bad_request(format!(
+                            "Unsupported timestamp granularity {}",
+                            value
+                        )));
+                    }
+                }
+            }
+            "language" => language = optional_text(&part)?,
+            "prompt" => prompt = optional_text(&part)?,
+            name => log::debug!("ignoring form field {}", name),
+        }
+    }
+
+    let file = file.ok_or_else(|| ApiError::bad_request("Missing file field"))?;
+    let extension = file
+        .filename
+        .as_deref()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_string());
+    let audio = capture::decode_bytes(file.data, extension.as_deref())
+        .map_err(|e| ApiError::bad_request(format!("{:#}", e)))?;
+
+    Ok(Upload {
+        audio: capture::AudioBuffer {
+            data: downmix(&audio.data, audio.channels, downmix_strategy),
+            sample_rate: audio.sample_rate,
+            channels: 1,
+        },
+        format,
+        word_timestamps,
+        language,
+        prompt,
+    })
+}
+
+/// The trimmed value of a text field, None when it is empty.
+fn optional_text(part: &multipart::Part) -> Result<Option<String>, ApiError> {
+    let value = part.text().map_err(ApiError::bad_request)?.trim();
+    Ok((!value.is_empty()).then(|| value.to_string()))
+}
+
+fn transcribe(
+    transcriber: &dyn whisper::Transcriber,
+    pipeline: &mut Pipeline,
+    language: &str,
+    task: whisper::Task,
+    upload: Upload,
+) -> Result<(ResponseFormat, String), ApiError> {
+    let audio = upload.audio;
+    info!(
+        "running {} on upload ({:.1}s)",
+        task.as_str(),
+        audio.duration()
+    );
+
+    let options = whisper::TranscribeOptions {
+        prompt: upload.prompt.as_deref(),
+        word_timestamps: upload.word_timestamps,
+        task: Some(task),
+        language: upload.language.as_deref(),
+        ..Default::default()
+    };
+    let mut output = transcriber.transcribe_with(
+        &whisper::InputAudio {
+            data: &audio.data,
+            sample_rate: audio.sample_rate,
+            channels: audio.channels,
+        },
+        options,
+    )?;
+    let language = output
+        .language
+        .as_ref()
+        .map(|detected| detected.code.clone())
+        .or(upload.language)
+        .unwrap_or_else(|| language.to_string());
+
+    // An upload is a finished recording, rewritten like one
+    let context = postprocess::Context {
+        language: match task {
+            whisper::Task::Translate => Some("en"),
+            whisper::Task::Transcribe => Some(&language),
+        },
+        final_pass: true,
+    };
+    pipeline.process(&mut output, &context);
+    let text = output.combined.trim();
+
+    let body = match upload.format {
+        ResponseFormat::Text => format!("{}\n", text),
+        ResponseFormat::Json => json!({ "text": text }).to_string(),
+        ResponseFormat::VerboseJson => {
+            let segments: Vec<_> = output
+                .segments
+                .iter()
+                .enumerate()
+                .map(|(id, segment)| {
+                    json!({
+                        "id": id,
+                        "start": segment.start as f64 / 100.0,
+                        "end": segment.end as f64 / 100.0,
+                        "text": segment.text,
+                        "avg_logprob": segment.confidence.ln(),
+                    })
+                })
+                .collect();
+            let mut body = json!({
+                "task": task.as_str(),
+                "language": language,
+                "duration": audio.duration(),
+                "text": text,
+                "segments": segments,
+            });
+            if upload.word_timestamps {
+                let words: Vec<_> = output
+                    .segments
+                    .iter()
+                    .flat_map(|segment| &segment.words)
+                    .map(|word| {
+                        json!({
+                            "word": word.text,
+                            "start": word.start as f64 / 100.0,
+                            "end": word.end as f64 / 100.0,
+                        })
+                    })
+                    .collect();
+                body["words"] = words.into();
+            }
+            body.to_string()
+        }
+        ResponseFormat::Srt => export::to_srt(&output.segments),
+        ResponseFormat::Vtt => export::to_vtt(&output.segments),
+    };
+
+    Ok((upload.format, body))
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).expect("valid header")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    use super::*;
+    use crate::whisper::{MockTranscriber, Segment};
+
+    /// A form with a short WAV upload and the given text fields.
+    fn form(fields: &[(&str, &str)]) -> Vec<u8> {
+        let samples: Vec<f32> = (0..2 * 16000)
+            .map(|i| ((i as f32) * 0.05).sin() * 0.2)
+            .collect();
+        let mut body = Vec::new();
+        for (name, value) in fields {
+            write!(
+                body,
+                "--xyz\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                name, value
+            )
+            .expect("write field");
+        }
+        body.extend_from_slice(
+            b"--xyz\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\n\r\n",
+        );
+        body.extend(capture::encode_wav(&samples, 16000));
+        body.extend_from_slice(b"\r\n--xyz--\r\n");
+        body
+    }
+
+    /// Posts `body` to a server answering with `transcriber` and returns
+    /// the status code and response body.
+    fn post(transcriber: &dyn whisper::Transcriber, body: Vec<u8>) -> (u16, serde_json::Value) {
+        let server = Server::http("127.0.0.1:0").expect("listen");
+        let addr = server.server_addr().to_ip().expect("ip address");
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).expect("connect");
+            write!(
+                stream,
+                "POST {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\
+                 Content-Type: multipart/form-data; boundary=xyz\r\n\
+                 Content-Length: {}\r\n\r\n",
+                TRANSCRIPTIONS_PATH,
+                addr,
+                body.len()
+            )
+            .expect("write headers");
+            stream.write_all(&body).expect("write body");
+            let mut response = String::new();
+            stream.read_to_string(&mut response).expect("read response");
+            response
+        });
+
+        let request = server.recv().expect("request");
+        let mut pipeline = Pipeline::new(&AppConfig::default(), None).expect("pipeline");
+        handle(
+            request,
+            transcriber,
+            &mut pipeline,
+            "en",
+            DownmixStrategy::Average,
+        );
+
+        let response = client.join().expect("client");
+        let (head, body) = response.split_once("\r\n\r\n").expect("response head");
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .expect("status code");
+        (status, serde_json::from_str(body).expect("json body"))
+    }
+
+    fn mock() -> MockTranscriber {
+        MockTranscriber::new(vec![vec![Segment {
+            start: 0,
+            end: 0,
+            text: " Hola.".to_string(),
+            confidence: 1.0,
+            words: Vec::new(),
+        }]])
+    }
+
+    #[test]
+    fn transcribes_an_upload() {
+        let body = form(&[
+            ("response_format", "verbose_json"),
+            ("language", "es"),
+            ("prompt", "Saludos"),
+        ]);
+        let (status, json) = post(&mock(), body);
+        assert_eq!(status, 200);
+        assert_eq!(json["text"], "Hola.");
+        assert_eq!(json["language"], "es");
+        assert_eq!(json["segments"][0]["end"], 2.0);
+    }
+
+    #[test]
+    fn rejects_bad_fields() {
+        let (status, json) = post(&mock(), form(&[("response_format", "xml")]));
+        assert_eq!(status, 400);
+        assert_eq!(json["error"]["type"], "invalid_request_error");
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::{Context, Result, bail};
+
+/// One field of a `multipart/form-data` body.
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Part {
+    pub fn text(&self) -> Result<&str> {
+        std::str::from_utf8(&self.data).with_context(|| format!("Field {} is not UTF-8", self.name))
+    }
+}
+
+/// Extracts the boundary from a `multipart/form-data` content type.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+
+    params.find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Splits a buffered `multipart/form-data` body into its parts.
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let separator = format!("\r\n--{}", boundary).into_bytes();
+
+    let start = find(body, &delimiter).context("Multipart boundary not found")?;
+    let mut rest = &body[start + delimiter.len()..];
+    let mut parts = Vec::new();
+
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = rest
+            .strip_prefix(b"\r\n")
+            .context("Malformed multipart boundary")?;
+
+        let header_end = find(rest, b"\r\n\r\n").context("Multipart headers not terminated")?;
+        let headers = std::str::from_utf8(&rest[..header_end]).context("Invalid part headers")?;
+        rest = &rest[header_end + 4..];
+
+        let data_end = find(rest, &separator).context("Multipart body not terminated")?;
+        let (name, filename) = content_disposition(headers)?;
+        parts.push(Part {
+            name,
+            filename,
+            data: rest[..data_end].to_vec(),
+        });
+        rest = &rest[data_end + separator.len()..];
+    }
+}
+
+/// Reads the field name and optional filename from the part headers.
+fn content_disposition(headers: &str) -> Result<(String, Option<String>)> {
+    let disposition = headers
+        .lines()
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim()
+                .eq_ignore_ascii_case("content-disposition")
+                .then_some(value)
+        })
+        .context("Part without Content-Disposition")?;
+
+    let mut name = None;
+    let mut filename = None;
+    for param in params(disposition) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_string();
+        match key.trim() {
+            "name" => name = Some(value),
+            "filename" => filename = Some(value),
+            _ => {}
+        }
+    }
+
+    match name {
+        Some(name) => Ok((name, filename)),
+        None => bail!("Part without a field name"),
+    }
+}
+
+/// Splits header parameters at the semicolons outside quoted values, so a
+/// filename like "take; 2.wav" stays whole.
+fn params(value: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(value[start..].trim());
+    params
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = b"preamble\r\n--xyz\r\n\
+Content-Disposition: form-data; name=\"model\"\r\n\r\n\
+whisper-1\r\n--xyz\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"take; 2.wav\"\r\n\
+Content-Type: audio/wav\r\n\r\n\
+RIFF\r\n--x\r\n--xyz--\r\n";
+
+    #[test]
+    fn reads_the_boundary() {
+        assert_eq!(
+            boundary("multipart/form-data; boundary=xyz").as_deref(),
+            Some("xyz")
+        );
+        assert_eq!(
+            boundary("Multipart/Form-Data; charset=utf-8; Boundary=\"a b\"").as_deref(),
+            Some("a b")
+        );
+        assert_eq!(boundary("multipart/form-data"), None);
+        assert_eq!(boundary("application/json; boundary=xyz"), None);
+    }
+
+    #[test]
+    fn splits_the_parts() {
+        let parts = parse(BODY, "xyz").expect("valid body");
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "model");
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].text().expect("text"), "whisper-1");
+        // Data may contain line breaks and most of the boundary
+        assert_eq!(parts[1].name, "file");
+        assert_eq!(parts[1].data, b"RIFF\r\n--x");
+    }
+
+    #[test]
+    fn keeps_quoted_semicolons() {
+        let parts = parse(BODY, "xyz").expect("valid body");
+        assert_eq!(parts[1].filename.as_deref(), Some("take; 2.wav"));
+    }
+
+    #[test]
+    fn rejects_truncated_bodies() {
+        for end in [40, 60, BODY.len() - 9, BODY.len() - 4] {
+            assert!(parse(&BODY[..end], "xyz").is_err(), "{} bytes", end);
+        }
+        assert!(parse(BODY, "other").is_err());
+    }
+}
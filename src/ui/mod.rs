@@ -2,32 +2,36 @@ use anyhow::Result;
 use arboard::Clipboard;
 use i_slint_backend_winit::WinitWindowAccessor;
 use log;
-use slint::{BackendSelector, Timer, TimerMode};
-use std::{sync::Arc, time::Duration};
-
-use crate::{capture, config::BehaviorConfig, whisper};
-use utils::{
-    handle_transcription_error, is_endswith_pattern, remove_end_pattern, transcribe_audio,
+use slint::BackendSelector;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    capture,
+    control::ControlCommand,
+    controller::{Controller, ControllerEvent, ControllerHandle, SessionState, Settings},
+    export::{self, ExportFormat},
+    whisper,
 };
 
-mod utils;
-
 slint::include_modules!();
 
+/// The last finished transcription, kept for "Save as".
+struct Transcript {
+    text: String,
+    segments: Vec<whisper::Segment>,
+}
+
 pub struct AppUI {
     window: Arc<MainWindow>,
-    recorder: Arc<capture::SimpleAudioCapture>,
-    transcriber: Arc<whisper::SimpleTranscriber>,
-    duration_timer: Arc<Timer>,
-    transcription_timer: Arc<Timer>,
-    behavior: BehaviorConfig,
+    controller: ControllerHandle,
+    transcript: Arc<Mutex<Option<Transcript>>>,
 }
 
 impl AppUI {
     pub fn new(
         recorder: Arc<capture::SimpleAudioCapture>,
-        transcriber: Arc<whisper::SimpleTranscriber>,
-        behavior: BehaviorConfig,
+        transcriber: Box<dyn whisper::Transcriber>,
+        settings: Settings,
     ) -> Result<Self> {
         let backend_selector = BackendSelector::new()
             .backend_name("winit".to_string())
@@ -36,16 +40,27 @@ impl AppUI {
         backend_selector.select()?;
 
         let window = Arc::new(MainWindow::new()?);
-        let duration_timer = Arc::new(Timer::default());
-        let transcription_timer = Arc::new(Timer::default());
+        let transcript = Arc::new(Mutex::new(None));
+
+        let controller = {
+            let window = window.as_weak();
+            let transcript = transcript.clone();
+            let auto_copy = settings.behavior.auto_copy;
+            Controller::spawn(recorder, transcriber, settings, move |event| {
+                let window = window.clone();
+                let transcript = transcript.clone();
+                let _ = slint::invoke_from_event_loop(move || {
+                    if let Some(window) = window.upgrade() {
+                        render_event(&window, &transcript, auto_copy, event);
+                    }
+                });
+            })
+        };
 
         let ui = Self {
             window,
-            recorder,
-            transcriber,
-            duration_timer,
-            transcription_timer,
-            behavior,
+            controller,
+            transcript,
         };
 
         ui.setup_handlers();
@@ -53,163 +68,35 @@ impl AppUI {
         Ok(ui)
     }
 
+    pub fn controller(&self) -> ControllerHandle {
+        self.controller.clone()
+    }
+
     fn setup_handlers(&self) {
         let window = self.window.clone();
-        let recorder = self.recorder.clone();
-        let duration_timer = self.duration_timer.clone();
-        let transcription_timer = self.transcription_timer.clone();
-        let behavior = self.behavior.clone();
-
-        // Duration timer function
-        let duration_timer_fn = {
-            let window = window.clone();
-            let recorder = recorder.clone();
-            Arc::new(move || {
-                let recording = recorder.get_is_recording();
-                if recording {
-                    if let Some(duration) = recorder.get_duration() {
-                        let minutes = duration as u32 / 60;
-                        let seconds = duration as u32 % 60;
-                        window.set_duration_minutes(format!("{:02}", minutes).into());
-                        window.set_duration_seconds(format!("{:02}", seconds).into());
-                    }
-                }
-            })
-        };
-
-        // Transcription timer function
-        let transcription_timer_fn = {
-            let window = window.clone();
-            let recorder = recorder.clone();
-            let transcriber = self.transcriber.clone();
-            let behavior = behavior.clone();
-            Arc::new(move || {
-                if !behavior.realtime_transcribe {
-                    return;
-                }
-                let recording = recorder.get_is_recording();
-                if recording {
-                    log::debug!("realtime transcribing audio");
-                    window.set_transcribing(true);
-                    match transcribe_audio(&transcriber, &recorder) {
-                        Ok(text) => {
-                            if !text.is_empty() {
-                                if behavior.stop_phrase_enabled
-                                    && is_endswith_pattern(&text, &behavior.stop_phrase_pattern)
-                                {
-                                    log::debug!("stopping phrase detected, stopping recording");
-                                    window.invoke_record_button_clicked();
-                                }
-
-                                if window.get_recording() {
-                                    window.set_transcription(text.into());
-                                    log::debug!("ui updated with realtime transcription");
-                                }
-                            }
-                        }
-                        Err(err) => handle_transcription_error(&window, err),
-                    }
-                }
-            })
-        };
 
         // Close button handler
         {
-            let recorder = recorder.clone();
-            let duration_timer = duration_timer.clone();
-            let transcription_timer = transcription_timer.clone();
+            let controller = self.controller.clone();
             self.window.on_close_button_clicked(move || {
-                recorder.clear();
-                duration_timer.stop();
-                transcription_timer.stop();
+                let _ = controller.send(ControlCommand::Cancel);
                 std::process::exit(0);
             });
         }
 
         // Record button handler
         {
-            let window = window.clone();
-            let recorder = recorder.clone();
-            let transcriber = self.transcriber.clone();
-            let duration_timer = duration_timer.clone();
-            let transcription_timer = transcription_timer.clone();
-            let behavior = behavior.clone();
-
+            let controller = self.controller.clone();
             self.window.on_record_button_clicked(move || {
-                let recording = window.get_recording();
-                if recording {
-                    recorder.pause();
-                    duration_timer.stop();
-                    transcription_timer.stop();
-
-                    log::debug!("final transcription");
-                    window.set_transcribing(true);
-                    match transcribe_audio(&transcriber, &recorder) {
-                        Ok(text) => {
-                            if !text.is_empty() {
-                                if behavior.stop_phrase_enabled {
-                                    if let Some(t) =
-                                        remove_end_pattern(&text, &behavior.stop_phrase_pattern)
-                                    {
-                                        log::debug!(
-                                            "transcribed text without stopping phrase: {}",
-                                            t
-                                        );
-                                        window.set_transcription(t.clone().into());
-                                        log::debug!("ui updated with transcription");
-                                        if behavior.auto_copy {
-                                            if let Ok(mut clipboard) = Clipboard::new() {
-                                                let _ = clipboard.set_text(t.to_string());
-                                            }
-                                        }
-                                    } else {
-                                        window.set_transcription(text.clone().into());
-                                        log::debug!("ui updated with transcription");
-                                        if behavior.auto_copy {
-                                            if let Ok(mut clipboard) = Clipboard::new() {
-                                                let _ = clipboard.set_text(text.to_string());
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    window.set_transcription(text.clone().into());
-                                    log::debug!("ui updated with transcription");
-                                    if behavior.auto_copy {
-                                        if let Ok(mut clipboard) = Clipboard::new() {
-                                            let _ = clipboard.set_text(text.to_string());
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(err) => handle_transcription_error(&window, err),
-                    }
-                    window.set_transcribing(false);
-                    window.set_recording(false);
-                } else {
-                    recorder.clear();
-                    recorder.start();
-                    window.set_transcription("".into());
-                    window.set_recording(true);
-
-                    let duration_timer_fn = duration_timer_fn.clone();
-                    duration_timer.start(
-                        TimerMode::Repeated,
-                        Duration::from_millis(500),
-                        move || {
-                            duration_timer_fn();
-                        },
-                    );
-
-                    let transcription_timer_fn = transcription_timer_fn.clone();
-                    transcription_timer.start(
-                        TimerMode::Repeated,
-                        Duration::from_millis(3000),
-                        move || {
-                            transcription_timer_fn();
-                        },
-                    );
-                }
+                controller.post(ControlCommand::Toggle);
+            });
+        }
+
+        // Task toggle handler
+        {
+            let controller = self.controller.clone();
+            self.window.on_translate_toggled(move || {
+                controller.post(ControlCommand::ToggleTranslate);
             });
         }
 
@@ -230,6 +117,19 @@ impl AppUI {
             });
         }
 
+        // Save button handler
+        {
+            let transcript = self.transcript.clone();
+            self.window.on_save_button_clicked(move || {
+                let Ok(transcript) = transcript.lock() else {
+                    return;
+                };
+                if let Some(transcript) = transcript.as_ref() {
+                    save_transcript(transcript);
+                }
+            });
+        }
+
         // Move window handler
         {
             let window = window.clone();
@@ -247,3 +147,104 @@ impl AppUI {
         Ok(())
     }
 }
+
+/// Asks for a file name and writes the transcript there.
+fn save_transcript(transcript: &Transcript) {
+    let Some(path) = rfd::FileDialog::new()
+        .set_title("Save transcription as")
+        .set_file_name("transcription.srt")
+        .add_filter("SubRip subtitles", &["srt"])
+        .add_filter("WebVTT subtitles", &["vtt"])
+        .add_filter("JSON", &["json"])
+        .add_filter("Text", &["txt"])
+        .save_file()
+    else {
+        return;
+    };
+
+    // The extension picks the format; `.txt` and anything unknown is plain text
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let format = match extension.as_str() {
+        "srt" => ExportFormat::Srt,
+        "vtt" => ExportFormat::Vtt,
+        "json" => ExportFormat::Json,
+        _ => ExportFormat::Text,
+    };
+    let output = export::export(format, &transcript.text, &transcript.segments);
+    match std::fs::write(&path, output) {
+        Ok(()) => log::info!("saved transcription to {}", path.display()),
+        Err(err) => log::error!("Failed to save {}: {}", path.display(), err),
+    }
+}
+
+fn render_event(
+    window: &MainWindow,
+    transcript: &Mutex<Option<Transcript>>,
+    auto_copy: bool,
+    event: ControllerEvent,
+) {
+    match event {
+        ControllerEvent::State(state) => {
+            if state.is_recording() {
+                if let Ok(mut transcript) = transcript.lock() {
+                    *transcript = None;
+                }
+                window.set_can_save(false);
+                // Realtime passes report no progress, so a bar left full by
+                // the last final pass would show on them
+                window.set_progress(0.0);
+            }
+            window.set_recording(state.is_recording());
+            window.set_transcribing(state.is_transcribing());
+            if let SessionState::Error(message) = state {
+                window.set_transcription(message.into());
+            }
+        }
+        ControllerEvent::Duration(duration) => {
+            let minutes = duration as u32 / 60;
+            let seconds = duration as u32 % 60;
+            window.set_duration_minutes(format!("{:02}", minutes).into());
+            window.set_duration_seconds(format!("{:02}", seconds).into());
+        }
+        ControllerEvent::Progress(progress) => window.set_progress(progress),
+        ControllerEvent::Text(text) | ControllerEvent::Error(text) => {
+            window.set_transcription(text.into());
+        }
+        ControllerEvent::Finished {
+            text,
+            segments,
+            submit,
+        } => {
+            if auto_copy || submit {
+                if let Ok(mut clipboard) = Clipboard::new() {
+                    let _ = clipboard.set_text(text.clone());
+                }
+            }
+            if let Ok(mut transcript) = transcript.lock() {
+                *transcript = Some(Transcript { text, segments });
+                window.set_can_save(true);
+            }
+        }
+        ControllerEvent::Task(task) => window.set_translate(task == whisper::Task::Translate),
+        ControllerEvent::Language(language) => {
+            let label = format!(
+                "{} {:.0}%",
+                language.code.to_uppercase(),
+                language.probability * 100.0
+            );
+            window.set_language(label.into());
+        }
+        ControllerEvent::Show => {
+            if let Err(e) = window.show() {
+                log::warn!("failed to show window: {}", e);
+            }
+            let _ = window.window().with_winit_window(|winit_win| {
+                winit_win.focus_window();
+            });
+        }
+    }
+}
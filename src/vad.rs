@@ -0,0 +1,192 @@
+use crate::config::VadConfig;
+
+/// Energy-based voice activity detector fed incrementally with captured audio.
+///
+/// Frames louder than `speech_threshold` start speech; speech ends once frames
+/// stay below `silence_threshold` for the hangover period. Levels are in dBFS.
+pub struct VoiceActivityDetector {
+    config: VadConfig,
+    frame_len: usize,
+    frame_secs: f32,
+    hangover_frames: usize,
+    pending: Vec<f32>,
+    processed: usize,
+    speaking: bool,
+    heard_speech: bool,
+    hangover_left: usize,
+    silence_frames: usize,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(config: VadConfig, sample_rate: u32, channels: usize) -> Self {
+        let frame_ms = config.frame_ms.max(1);
+        let frame_len = (sample_rate as usize * frame_ms as usize / 1000).max(1) * channels;
+        let hangover_frames = (config.hangover_ms / frame_ms) as usize;
+
+        Self {
+            config,
+            frame_len,
+            frame_secs: frame_ms as f32 / 1000.0,
+            hangover_frames,
+            pending: Vec::with_capacity(frame_len),
+            processed: 0,
+            speaking: false,
+            heard_speech: false,
+            hangover_left: 0,
+            silence_frames: 0,
+        }
+    }
+
+    /// Number of interleaved samples consumed so far.
+    pub fn processed(&self) -> usize {
+        self.processed
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        self.processed += samples.len();
+
+        let mut samples = samples;
+        while !samples.is_empty() {
+            let take = (self.frame_len - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+
+            if self.pending.len() == self.frame_len {
+                let level = frame_level(&self.pending);
+                self.pending.clear();
+                self.push_frame(level);
+            }
+        }
+    }
+
+    fn push_frame(&mut self, level: f32) {
+        if level >= self.config.speech_threshold
+            || (self.speaking && level >= self.config.silence_threshold)
+        {
+            self.speaking = true;
+            self.heard_speech = true;
+            self.hangover_left = self.hangover_frames;
+            self.silence_frames = 0;
+            return;
+        }
+
+        if self.speaking {
+            if self.hangover_left > 0 {
+                self.hangover_left -= 1;
+                return;
+            }
+            self.speaking = false;
+        }
+        self.silence_frames += 1;
+    }
+
+    /// Seconds of silence since speech last ended, not counting the hangover.
+    pub fn trailing_silence(&self) -> f32 {
+        if self.speaking {
+            0.0
+        } else {
+            self.silence_frames as f32 * self.frame_secs
+        }
+    }
+
+    /// True once speech has been heard and followed by enough silence.
+    pub fn should_stop(&self) -> bool {
+        self.heard_speech && !self.speaking && self.trailing_silence() >= self.config.silence_secs
+    }
+}
+
+fn frame_level(frame: &[f32]) -> f32 {
+    let energy = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+    10.0 * energy.max(1e-10).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 ms frames of 10 samples, 3 frames of hangover, stopping after 5
+    /// silent frames.
+    fn detector() -> VoiceActivityDetector {
+        let config = VadConfig {
+            silence_secs: 0.045,
+            hangover_ms: 30,
+            frame_ms: 10,
+            ..VadConfig::default()
+        };
+        VoiceActivityDetector::new(config, 1000, 1)
+    }
+
+    /// `count` frames at a constant amplitude: 0.1 is -20 dBFS, 0.005 is
+    /// -46 dBFS and 0.001 is -60 dBFS.
+    fn frames(amplitude: f32, count: usize) -> Vec<f32> {
+        vec![amplitude; count * 10]
+    }
+
+    const LOUD: f32 = 0.1;
+    const MURMUR: f32 = 0.005;
+    const QUIET: f32 = 0.001;
+
+    #[test]
+    fn speech_starts_above_the_speech_threshold() {
+        let mut vad = detector();
+        vad.process(&frames(MURMUR, 20));
+        assert!(!vad.speaking);
+        assert!(!vad.should_stop());
+
+        vad.process(&frames(LOUD, 1));
+        assert!(vad.speaking);
+        assert_eq!(vad.trailing_silence(), 0.0);
+
+        // Between the thresholds speech goes on
+        vad.process(&frames(MURMUR, 20));
+        assert!(vad.speaking);
+    }
+
+    #[test]
+    fn hangover_holds_speech() {
+        let mut vad = detector();
+        vad.process(&frames(LOUD, 1));
+        vad.process(&frames(QUIET, 3));
+        assert!(vad.speaking);
+        assert_eq!(vad.trailing_silence(), 0.0);
+
+        vad.process(&frames(QUIET, 1));
+        assert!(!vad.speaking);
+        assert!((vad.trailing_silence() - 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stops_after_trailing_silence() {
+        let mut vad = detector();
+        vad.process(&frames(QUIET, 50));
+        assert!(!vad.should_stop(), "silence without speech never stops");
+
+        vad.process(&frames(LOUD, 2));
+        vad.process(&frames(QUIET, 3 + 4));
+        assert!(!vad.should_stop());
+        vad.process(&frames(QUIET, 1));
+        assert!(vad.should_stop());
+
+        // Speaking again starts over
+        vad.process(&frames(LOUD, 1));
+        assert!(!vad.should_stop());
+    }
+
+    #[test]
+    fn frames_span_calls() {
+        let mut vad = VoiceActivityDetector::new(
+            VadConfig {
+                frame_ms: 10,
+                ..VadConfig::default()
+            },
+            1000,
+            2,
+        );
+        let loud = [LOUD; 20];
+        vad.process(&loud[..7]);
+        assert!(!vad.speaking);
+        vad.process(&loud[7..]);
+        assert!(vad.speaking);
+        assert_eq!(vad.processed(), 20);
+    }
+}
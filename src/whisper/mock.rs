@@ -0,0 +1,149 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{InputAudio, Result, Segment, TranscribeOptions, TranscribeOutput, Transcriber, Word};
+use crate::error::SpeakError;
+
+const DEFAULT_SCRIPT: &str = "This is a mock transcription.";
+
+/// Returns canned transcripts instead of running a model, so the rest of the
+/// app can be exercised without a GPU or model file.
+///
+/// Each pass returns the next scripted result; once the script runs out the
+/// last entry is repeated.
+pub struct MockTranscriber {
+    script: Vec<Vec<Segment>>,
+    calls: AtomicUsize,
+    word_timestamps: bool,
+}
+
+impl MockTranscriber {
+    /// Scripted passes made of explicit segments. Segment times are used as
+    /// given, in centiseconds from the start of the audio.
+    pub fn new(script: Vec<Vec<Segment>>) -> Self {
+        Self {
+            script,
+            calls: AtomicUsize::new(0),
+            word_timestamps: false,
+        }
+    }
+
+    /// Spreads the words of scripted segments that have none evenly over
+    /// the segment.
+    pub fn with_word_timestamps(mut self, enabled: bool) -> Self {
+        self.word_timestamps = enabled;
+        self
+    }
+
+    /// Reads a script with one pass per line; blank lines produce empty
+    /// passes. Each line becomes a single segment spanning the whole audio.
+    pub fn from_script(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| SpeakError::ModelLoad {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        Ok(Self::from_lines(contents.lines()))
+    }
+
+    fn from_lines<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        let script = lines
+            .into_iter()
+            .map(|line| {
+                let text = line.trim();
+                if text.is_empty() {
+                    return Vec::new();
+                }
+                vec![Segment {
+                    start: 0,
+                    end: 0,
+                    // Whisper segments carry their leading space
+                    text: format!(" {}", text),
+                    confidence: 1.0,
+                    words: Vec::new(),
+                }]
+            })
+            .collect();
+        Self::new(script)
+    }
+
+    /// How many passes have been run so far.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    fn next_segments(&self) -> Vec<Segment> {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed);
+        match self.script.get(call).or(self.script.last()) {
+            Some(segments) => segments.clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Default for MockTranscriber {
+    fn default() -> Self {
+        Self::from_lines([DEFAULT_SCRIPT])
+    }
+}
+
+impl Transcriber for MockTranscriber {
+    fn transcribe_with(
+        &self,
+        audio: &InputAudio,
+        options: TranscribeOptions,
+    ) -> Result<TranscribeOutput> {
+        let frames = audio.data.len() / audio.channels.max(1);
+        let duration_cs = frames * 100 / audio.sample_rate.max(1) as usize;
+        if duration_cs < 100 {
+            return Err(SpeakError::AudioTooShort);
+        }
+
+        let word_timestamps = options.word_timestamps || self.word_timestamps;
+        let segments: Vec<Segment> = self
+            .next_segments()
+            .into_iter()
+            .map(|mut segment| {
+                // Whole-audio segments from a line script end where the audio does
+                if segment.end == 0 {
+                    segment.end = duration_cs;
+                }
+                segment.end = segment.end.min(duration_cs);
+                segment.start = segment.start.min(segment.end);
+                if word_timestamps && segment.words.is_empty() {
+                    segment.words = spread_words(&segment);
+                }
+                segment
+            })
+            .collect();
+
+        if let Some(mut on_progress) = options.on_progress {
+            on_progress(100);
+        }
+
+        let combined = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect();
+
+        Ok(TranscribeOutput {
+            combined,
+            segments,
+            language: None,
+        })
+    }
+}
+
+fn spread_words(segment: &Segment) -> Vec<Word> {
+    let texts: Vec<&str> = segment.text.split_whitespace().collect();
+    let step = (segment.end - segment.start) / texts.len().max(1);
+    texts
+        .iter()
+        .enumerate()
+        .map(|(index, text)| Word {
+            start: segment.start + index * step,
+            end: segment.start + (index + 1) * step,
+            text: text.to_string(),
+            probability: segment.confidence,
+        })
+        .collect()
+}
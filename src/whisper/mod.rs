@@ -1,11 +1,101 @@
 #![allow(dead_code)]
 
-use anyhow::Result;
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
 
 use crate::config::WhisperConfig;
+use crate::downmix::{DownmixStrategy, downmix};
+use crate::error::SpeakError;
 
+mod mock;
+mod prompt;
 mod resample;
+mod stream;
+
+type Result<T> = std::result::Result<T, SpeakError>;
+
+pub use mock::MockTranscriber;
+pub use prompt::Vocabulary;
+pub use stream::StreamState;
+
+/// Which engine `load` creates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// whisper.cpp with the GGML model at `model_path`
+    #[default]
+    Whisper,
+    /// Scripted transcripts without loading a model, for tests and demos
+    Mock,
+}
+
+/// `WhisperConfig::language` value that detects the language of each pass.
+pub const AUTO_LANGUAGE: &str = "auto";
+
+/// What whisper produces from the audio.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Task {
+    /// Text in the spoken language
+    #[default]
+    Transcribe,
+    /// English text, whatever the spoken language
+    Translate,
+}
+
+impl Task {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Transcribe => "transcribe",
+            Self::Translate => "translate",
+        }
+    }
+}
+
+/// Per-pass options; backends ignore what they do not support.
+#[derive(Default)]
+pub struct TranscribeOptions<'a> {
+    /// Text the audio is expected to continue from, added after the
+    /// configured prompt
+    pub prompt: Option<&'a str>,
+    /// Called with the completion percentage
+    pub on_progress: Option<Box<dyn FnMut(i32)>>,
+    /// Fill `Segment::words`, even when `word_timestamps` is off in the config
+    pub word_timestamps: bool,
+    /// Overrides the configured task
+    pub task: Option<Task>,
+    /// Overrides the configured language; `AUTO_LANGUAGE` detects it
+    pub language: Option<&'a str>,
+}
+
+/// A speech-to-text engine.
+pub trait Transcriber: Send {
+    fn transcribe_with(
+        &self,
+        audio: &InputAudio,
+        options: TranscribeOptions,
+    ) -> Result<TranscribeOutput>;
+
+    fn transcribe(&self, audio: &InputAudio) -> Result<TranscribeOutput> {
+        self.transcribe_with(audio, TranscribeOptions::default())
+    }
+}
+
+/// Creates the backend selected in `config`.
+pub fn load(config: WhisperConfig) -> Result<Box<dyn Transcriber>> {
+    match config.backend {
+        Backend::Whisper => Ok(Box::new(SimpleTranscriber::new(config)?)),
+        Backend::Mock => {
+            log::info!("using the mock transcriber, no model is loaded");
+            let mock = match &config.mock_script {
+                Some(path) => MockTranscriber::from_script(path)?,
+                None => MockTranscriber::default(),
+            };
+            Ok(Box::new(mock.with_word_timestamps(config.word_timestamps)))
+        }
+    }
+}
 
 pub struct InputAudio<'a> {
     pub data: &'a [f32],
@@ -13,16 +103,40 @@ pub struct InputAudio<'a> {
     pub channels: usize,
 }
 
+#[derive(Clone, Debug, Default)]
 pub struct TranscribeOutput {
     pub combined: String,
     pub segments: Vec<Segment>,
+    /// Set when the language was detected rather than configured
+    pub language: Option<DetectedLanguage>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetectedLanguage {
+    /// Whisper's language code, e.g. "en"
+    pub code: String,
+    pub probability: f32,
 }
 
+#[derive(Clone, Debug)]
 pub struct Segment {
     pub start: usize,
     pub end: usize,
     pub text: String,
     pub confidence: f32,
+    /// Empty unless word timestamps were requested
+    pub words: Vec<Word>,
+}
+
+/// A word of a segment, timed from whisper's token timestamps.
+#[derive(Clone, Debug)]
+pub struct Word {
+    /// Centiseconds, like the segment times
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// Lowest probability among the word's tokens
+    pub probability: f32,
 }
 
 impl PartialEq for Segment {
@@ -34,6 +148,8 @@ impl PartialEq for Segment {
 pub struct SimpleTranscriber {
     ctx: WhisperContext,
     config: WhisperConfig,
+    /// `initial_prompt` and vocabulary, resolved once at load
+    prompt: Option<String>,
 }
 
 impl SimpleTranscriber {
@@ -41,30 +157,60 @@ impl SimpleTranscriber {
         let mut ctx_params = WhisperContextParameters::default();
         ctx_params.use_gpu(config.use_gpu);
 
-        let ctx = WhisperContext::new_with_params(config.model_path.to_str().unwrap(), ctx_params)
-            .map_err(|e| anyhow::anyhow!("Failed to load model: {}", e))?;
+        let model_load = |reason: String| SpeakError::ModelLoad {
+            path: config.model_path.clone(),
+            reason,
+        };
+        let model_path = config
+            .model_path
+            .to_str()
+            .ok_or_else(|| model_load("path is not valid UTF-8".to_string()))?;
+        if !config.model_path.is_file() {
+            return Err(model_load("file does not exist".to_string()));
+        }
+        let prompt = prompt::base_prompt(&config)?;
+        let ctx = WhisperContext::new_with_params(model_path, ctx_params)
+            .map_err(|e| model_load(e.to_string()))?;
 
-        Ok(Self { ctx, config })
+        Ok(Self {
+            ctx,
+            config,
+            prompt,
+        })
     }
 
-    pub fn transcribe(&self, audio_data: &InputAudio) -> Result<TranscribeOutput> {
-        let resampled_audio = match resample::resample_to_16khz(
+    fn run(&self, audio_data: &InputAudio, options: TranscribeOptions) -> Result<TranscribeOutput> {
+        let mono_audio = downmix(
             audio_data.data,
-            audio_data.sample_rate,
             audio_data.channels,
-        ) {
-            Ok(audio) => audio,
-            Err(_) => return Err(anyhow::anyhow!("failed to resample audio")),
-        };
-        if resampled_audio.len() < 16000 {
-            return Err(anyhow::anyhow!("resampled audio is too short"));
+            DownmixStrategy::Average,
+        );
+        let mono_audio = resample::resample_to_16khz(&mono_audio, audio_data.sample_rate, 1)
+            .map_err(|e| SpeakError::Resample(e.to_string()))?;
+        if mono_audio.len() < 16000 {
+            return Err(SpeakError::AudioTooShort);
         }
 
-        let mono_audio = whisper_rs::convert_stereo_to_mono_audio(&resampled_audio)
-            .expect("failed to convert audio to mono");
+        let word_timestamps = options.word_timestamps || self.config.word_timestamps;
+        let task = options.task.unwrap_or(self.config.task);
+        let mut state = self.ctx.create_state()?;
+
+        let language = options.language.unwrap_or(&self.config.language);
+        let detected = if language == AUTO_LANGUAGE {
+            Some(self.detect_language(&mut state, &mono_audio)?)
+        } else if whisper_rs::get_lang_id(language).is_none() {
+            return Err(SpeakError::UnknownLanguage(language.to_string()));
+        } else {
+            None
+        };
+        let language = match &detected {
+            Some(detected) => detected.code.as_str(),
+            None => language,
+        };
 
         let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-        params.set_language(Some(&self.config.language));
+        params.set_language(Some(language));
+        params.set_translate(task == Task::Translate);
         params.set_print_special(false);
         params.set_print_progress(false);
         params.set_print_realtime(false);
@@ -72,38 +218,42 @@ impl SimpleTranscriber {
         params.set_audio_ctx(self.config.audio_context);
         params.set_no_speech_thold(self.config.no_speech_threshold);
         params.set_n_threads(self.config.num_threads);
+        if let Some(prompt) = prompt::combine(self.prompt.as_deref(), options.prompt) {
+            params.set_initial_prompt(&prompt.replace('\0', ""));
+        }
+        if let Some(on_progress) = options.on_progress {
+            params.set_progress_callback_safe(on_progress);
+        }
+        params.set_token_timestamps(word_timestamps);
 
         // Run the model
-        let mut state = self.ctx.create_state().expect("failed to create state");
-        state
-            .full(params, &mono_audio[..])
-            .expect("failed to run model");
+        state.full(params, &mono_audio[..])?;
 
         // Fetch the results
-        let num_segments = state
-            .full_n_segments()
-            .expect("failed to get number of segments");
+        let num_segments = state.full_n_segments()?;
 
         let mut combined = String::new();
         let mut segments = Vec::new();
+        let eot = self.ctx.token_eot();
 
         for i in 0..num_segments {
-            let text = state
-                .full_get_segment_text(i)
-                .expect("failed to get segment");
-            let start = state
-                .full_get_segment_t0(i)
-                .expect("failed to get segment start timestamp");
-            let end = state
-                .full_get_segment_t1(i)
-                .expect("failed to get segment end timestamp");
+            let text = state.full_get_segment_text(i)?;
+            let start = state.full_get_segment_t0(i)?;
+            let end = state.full_get_segment_t1(i)?;
 
             let n_tok = state.full_n_tokens(i)?;
             let mut sum_logprob = 0.0_f32;
+            let mut words = WordBuilder::default();
 
             for t in 0..n_tok {
                 let tok = state.full_get_token_data(i, t)?; // tok.plog is log-p
                 sum_logprob += tok.plog;
+
+                // Ids from the end-of-text token on are special tokens, not text
+                if word_timestamps && tok.id < eot {
+                    let bytes = self.ctx.token_to_cstr(tok.id)?.to_bytes();
+                    words.push_token(bytes, tok.t0, tok.t1, tok.p);
+                }
             }
 
             let avg_logprob = sum_logprob / n_tok as f32;
@@ -116,9 +266,88 @@ impl SimpleTranscriber {
                 end: end as usize,
                 text: text,
                 confidence: confidence,
+                words: words.finish(),
             });
         }
 
-        Ok(TranscribeOutput { combined, segments })
+        Ok(TranscribeOutput {
+            combined,
+            segments,
+            language: detected,
+        })
+    }
+
+    /// Runs whisper's language detection on the start of `audio` (16 kHz mono).
+    fn detect_language(&self, state: &mut WhisperState, audio: &[f32]) -> Result<DetectedLanguage> {
+        let threads = self.config.num_threads.max(1) as usize;
+        state.pcm_to_mel(audio, threads)?;
+        let (id, probabilities) = state.lang_detect(0, threads)?;
+
+        let code = whisper_rs::get_lang_str(id)
+            .ok_or_else(|| SpeakError::Inference(format!("unknown language id {}", id)))?;
+        let probability = probabilities.get(id as usize).copied().unwrap_or_default();
+        log::debug!("detected language {} ({:.0}%)", code, probability * 100.0);
+
+        Ok(DetectedLanguage {
+            code: code.to_string(),
+            probability,
+        })
+    }
+}
+
+impl Transcriber for SimpleTranscriber {
+    fn transcribe_with(
+        &self,
+        audio: &InputAudio,
+        options: TranscribeOptions,
+    ) -> Result<TranscribeOutput> {
+        self.run(audio, options)
+    }
+}
+
+/// Joins tokens into words: a token starting with a space begins a new word,
+/// anything else continues the current one.
+#[derive(Default)]
+struct WordBuilder {
+    words: Vec<Word>,
+    /// Raw bytes, since a token may end in the middle of a UTF-8 character
+    text: Vec<u8>,
+    start: usize,
+    end: usize,
+    probability: f32,
+}
+
+impl WordBuilder {
+    fn push_token(&mut self, bytes: &[u8], t0: i64, t1: i64, probability: f32) {
+        if bytes.starts_with(b" ") {
+            self.flush();
+        }
+
+        if self.text.is_empty() {
+            self.start = t0.max(0) as usize;
+            self.probability = probability;
+        } else {
+            self.probability = self.probability.min(probability);
+        }
+        self.end = t1.max(0) as usize;
+        self.text.extend_from_slice(bytes);
+    }
+
+    fn flush(&mut self) {
+        let text = String::from_utf8_lossy(&self.text).trim().to_string();
+        if !text.is_empty() {
+            self.words.push(Word {
+                start: self.start,
+                end: self.end.max(self.start),
+                text,
+                probability: self.probability,
+            });
+        }
+        self.text.clear();
+    }
+
+    fn finish(mut self) -> Vec<Word> {
+        self.flush();
+        self.words
     }
 }
@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+
+use super::Result;
+use crate::config::WhisperConfig;
+use crate::error::SpeakError;
+
+/// Terms whisper should prefer, listed inline or read from a word file.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum Vocabulary {
+    Words(Vec<String>),
+    /// One word or phrase per line; blank lines and `#` comments are skipped
+    File(PathBuf),
+}
+
+impl Vocabulary {
+    fn words(&self) -> Result<Vec<String>> {
+        match self {
+            Self::Words(words) => Ok(words.clone()),
+            Self::File(path) => read_word_file(path),
+        }
+    }
+}
+
+fn read_word_file(path: &Path) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| SpeakError::WordFile {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    })?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// The prompt every pass starts from: `initial_prompt` followed by the
+/// vocabulary as a comma-separated list, so whisper has seen the spelling.
+pub fn base_prompt(config: &WhisperConfig) -> Result<Option<String>> {
+    let mut parts = Vec::new();
+
+    let prompt = config.initial_prompt.as_deref().map(str::trim);
+    if let Some(prompt) = prompt.filter(|p| !p.is_empty()) {
+        parts.push(prompt.to_string());
+    }
+
+    let words = match &config.vocabulary {
+        Some(vocabulary) => vocabulary.words()?,
+        None => Vec::new(),
+    };
+    if !words.is_empty() {
+        log::debug!("biasing transcription towards {} terms", words.len());
+        parts.push(format!("{}.", words.join(", ")));
+    }
+
+    Ok((!parts.is_empty()).then(|| parts.join(" ")))
+}
+
+/// Joins the base prompt and the text a pass continues from.
+pub fn combine(base: Option<&str>, context: Option<&str>) -> Option<String> {
+    match (base, context) {
+        (Some(base), Some(context)) => Some(format!("{} {}", base, context)),
+        (base, context) => base.or(context).map(str::to_string),
+    }
+}
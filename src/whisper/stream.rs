@@ -0,0 +1,257 @@
+use super::{
+    DetectedLanguage, InputAudio, Result, Segment, Task, TranscribeOptions, TranscribeOutput,
+    Transcriber, Word,
+};
+
+/// Window audio shorter than this (in centiseconds) is not worth a realtime
+/// pass; on the final pass it is padded with silence up to this length.
+const MIN_WINDOW_CS: usize = 150;
+/// Upper bound on the committed text handed to Whisper as the prompt.
+const MAX_PROMPT_CHARS: usize = 200;
+
+/// Rolling state for incremental transcription of a growing recording.
+///
+/// Segments that come out identical on two consecutive passes are committed:
+/// their text is frozen and the audio they cover is dropped from the window
+/// that the next pass transcribes. Segment timestamps are in centiseconds
+/// from the start of the recording.
+#[derive(Default)]
+pub struct StreamState {
+    committed: Vec<Segment>,
+    committed_until: usize,
+    tentative: Vec<Segment>,
+    /// Detected on the first pass of the recording and reused for the
+    /// rest of it, when the language is detected
+    language: Option<DetectedLanguage>,
+}
+
+impl StreamState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn committed_text(&self) -> String {
+        self.committed.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    fn prompt(&self) -> Option<String> {
+        let text = self.committed_text();
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        let mut start = text.len().saturating_sub(MAX_PROMPT_CHARS);
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        Some(text[start..].to_string())
+    }
+
+    fn window_start_frame(&self, sample_rate: u32) -> usize {
+        self.committed_until * sample_rate as usize / 100
+    }
+
+    /// Merges the segments of one pass over the window into the stream.
+    fn update(&mut self, window: Vec<Segment>, window_len: usize, finalize: bool) {
+        let offset = self.committed_until;
+        let window: Vec<Segment> = window
+            .into_iter()
+            .map(|s| Segment {
+                start: s.start + offset,
+                end: s.end + offset,
+                words: s
+                    .words
+                    .into_iter()
+                    .map(|w| Word {
+                        start: w.start + offset,
+                        end: w.end + offset,
+                        ..w
+                    })
+                    .collect(),
+                ..s
+            })
+            .collect();
+
+        // The last segment may still be cut off mid-word, so it is only
+        // committed on the final pass.
+        let stable = if finalize {
+            window.len()
+        } else {
+            window
+                .iter()
+                .take(window.len().saturating_sub(1))
+                .zip(self.tentative.iter())
+                .take_while(|(current, previous)| {
+                    current.text.trim() == previous.text.trim()
+                        && current.end + MIN_WINDOW_CS <= offset + window_len
+                })
+                .count()
+        };
+
+        let mut window = window;
+        let tentative = window.split_off(stable);
+        if let Some(last) = window.last() {
+            self.committed_until = last.end;
+        }
+        self.committed.extend(window);
+        self.tentative = tentative;
+    }
+
+    fn output(&self) -> TranscribeOutput {
+        let segments: Vec<Segment> = self
+            .committed
+            .iter()
+            .chain(self.tentative.iter())
+            .cloned()
+            .collect();
+        let combined = segments.iter().map(|s| s.text.as_str()).collect();
+
+        TranscribeOutput {
+            combined,
+            segments,
+            language: self.language.clone(),
+        }
+    }
+}
+
+impl StreamState {
+    /// Transcribes only the uncommitted tail of `audio_data`, which must hold
+    /// the whole recording so far. With `finalize` every remaining segment is
+    /// committed and the state is ready for the next recording.
+    pub fn transcribe<F>(
+        &mut self,
+        transcriber: &dyn Transcriber,
+        audio_data: &InputAudio,
+        task: Task,
+        finalize: bool,
+        on_progress: F,
+    ) -> Result<TranscribeOutput>
+    where
+        F: FnMut(i32) + 'static,
+    {
+        let frames = audio_data.data.len() / audio_data.channels;
+        let start_frame = self.window_start_frame(audio_data.sample_rate);
+        if start_frame > frames {
+            log::debug!("audio is shorter than the committed stream, starting over");
+            self.reset();
+        }
+
+        let start_frame = self.window_start_frame(audio_data.sample_rate);
+        let window_len = (frames - start_frame) * 100 / audio_data.sample_rate as usize;
+
+        if window_len >= MIN_WINDOW_CS || (finalize && window_len > 0) {
+            let mut data = audio_data.data[start_frame * audio_data.channels..].to_vec();
+            let min_samples =
+                MIN_WINDOW_CS * audio_data.sample_rate as usize / 100 * audio_data.channels;
+            if data.len() < min_samples {
+                data.resize(min_samples, 0.0);
+            }
+
+            let window = InputAudio {
+                data: &data,
+                sample_rate: audio_data.sample_rate,
+                channels: audio_data.channels,
+            };
+            let prompt = self.prompt();
+            let options = TranscribeOptions {
+                prompt: prompt.as_deref(),
+                on_progress: Some(Box::new(on_progress)),
+                task: Some(task),
+                language: self
+                    .language
+                    .as_ref()
+                    .map(|detected| detected.code.as_str()),
+                ..Default::default()
+            };
+            let output = transcriber.transcribe_with(&window, options)?;
+            if output.language.is_some() {
+                self.language = output.language;
+            }
+            self.update(output.segments, window_len, finalize);
+        }
+
+        let output = self.output();
+        if finalize {
+            self.reset();
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Detects German whenever no language is given, and records the
+    /// language of each pass.
+    #[derive(Default)]
+    struct Detecting {
+        languages: Mutex<Vec<Option<String>>>,
+    }
+
+    impl Transcriber for Detecting {
+        fn transcribe_with(
+            &self,
+            _audio: &InputAudio,
+            options: TranscribeOptions,
+        ) -> Result<TranscribeOutput> {
+            self.languages
+                .lock()
+                .unwrap()
+                .push(options.language.map(str::to_string));
+            let language = options.language.is_none().then(|| DetectedLanguage {
+                code: "de".to_string(),
+                probability: 0.9,
+            });
+            Ok(TranscribeOutput {
+                language,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn pass(
+        stream: &mut StreamState,
+        transcriber: &Detecting,
+        seconds: usize,
+        finalize: bool,
+    ) -> TranscribeOutput {
+        let data = vec![0.0; seconds * 16000];
+        let audio = InputAudio {
+            data: &data,
+            sample_rate: 16000,
+            channels: 1,
+        };
+        stream
+            .transcribe(transcriber, &audio, Task::Transcribe, finalize, |_| {})
+            .expect("pass")
+    }
+
+    #[test]
+    fn detects_the_language_once_per_recording() {
+        let transcriber = Detecting::default();
+        let mut stream = StreamState::new();
+
+        for (seconds, finalize) in [(3, false), (5, false), (6, true), (3, false)] {
+            let output = pass(&mut stream, &transcriber, seconds, finalize);
+            assert_eq!(
+                output.language.map(|detected| detected.code),
+                Some("de".into())
+            );
+        }
+
+        let de = Some("de".to_string());
+        assert_eq!(
+            *transcriber.languages.lock().unwrap(),
+            [None, de.clone(), de, None]
+        );
+    }
+}